@@ -0,0 +1,225 @@
+//! Writes a PPD file for a Brother QL printer model to stdout
+//!
+//! Usage: `brother-ql-ppd MODEL [--labels FILE]`, for example `brother-ql-ppd QL-700`. Every known label
+//! the model can print on becomes a page size, including the labels of the label file, whose path is
+//! stored in the PPD so the filter loads them too.
+use std::env;
+use std::fs;
+use std::process;
+
+use brother_ql_cups::{page_size_name, points, LABELS_KEYWORD};
+use brother_ql_rs::printer::catalogue;
+use brother_ql_rs::printer::constants::{known_labels, Label};
+use brother_ql_rs::printer::model::PrinterModel;
+use brother_ql_rs::printer::registry;
+
+/// Length of the page sizes for continuous tape, longer labels need a custom page size
+const CONTINUOUS_LENGTH_MM: f64 = 100.0;
+/// Width of the continuous tape chosen by default, if the model supports it
+const DEFAULT_WIDTH_MM: u32 = 62;
+/// Longest custom page size
+const MAX_LENGTH_MM: f64 = 1000.0;
+/// Resolution of the print head, in dots per inch
+const DPI: f64 = 300.0;
+
+fn main() {
+    let mut model = None;
+    let mut labels_file = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), model.is_none()) {
+            ("--labels", _) => match args.next() {
+                Some(path) => labels_file = Some(path),
+                None => usage(),
+            },
+            (_, true) if !arg.starts_with('-') => model = Some(arg),
+            _ => usage(),
+        }
+    }
+    let name = model.unwrap_or_else(|| usage());
+    let model = PrinterModel::from_name(&name);
+    if model == PrinterModel::Unknown {
+        eprintln!("Unknown printer model {}", name);
+        process::exit(2);
+    }
+
+    if let Some(path) = &labels_file {
+        if let Err(error) = registry::load_labels(path) {
+            eprintln!("Can't load labels from {}: {}", path, error);
+            process::exit(1);
+        }
+    }
+    // Store an absolute path, as the filter doesn't run in the current directory
+    let labels_file = labels_file.map(|path| {
+        fs::canonicalize(&path)
+            .map(|path| path.display().to_string())
+            .unwrap_or(path)
+    });
+
+    let labels: Vec<Label> = known_labels()
+        .into_iter()
+        .filter(|label| {
+            catalogue::find_by_size(label.tape_size.0, label.tape_size.1)
+                .is_none_or(|entry| entry.supports_model(&model))
+        })
+        .collect();
+    print!("{}", ppd(&model, &labels, labels_file.as_deref()));
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: brother-ql-ppd MODEL [--labels FILE]");
+    process::exit(2);
+}
+
+fn ppd(model: &PrinterModel, labels: &[Label], labels_file: Option<&str>) -> String {
+    let name = model.to_str();
+    let mut ppd = String::new();
+    let mut line = |text: String| {
+        ppd.push_str(&text);
+        ppd.push('\n');
+    };
+
+    line("*PPD-Adobe: \"4.3\"".into());
+    line("*FormatVersion: \"4.3\"".into());
+    line(format!("*FileVersion: \"{}\"", env!("CARGO_PKG_VERSION")));
+    line("*LanguageVersion: English".into());
+    line("*LanguageEncoding: ISOLatin1".into());
+    line(format!("*PCFileName: \"{}.PPD\"", name.replace(['-', '/'], "")));
+    line("*Manufacturer: \"Brother\"".into());
+    line(format!("*Product: \"({})\"", name));
+    line(format!("*ModelName: \"Brother {}\"", name));
+    line(format!("*ShortNickName: \"Brother {}\"", name));
+    line(format!("*NickName: \"Brother {}, brother-ql-rs\"", name));
+    line("*PSVersion: \"(3010.000) 0\"".into());
+    line("*LanguageLevel: \"3\"".into());
+    line("*ColorDevice: False".into());
+    line("*DefaultColorSpace: Gray".into());
+    line("*FileSystem: False".into());
+    line("*Throughput: \"1\"".into());
+    line("*LandscapeOrientation: Plus90".into());
+    line("*TTRasterizer: Type42".into());
+    line("*cupsVersion: 2.2".into());
+    line("*cupsModelNumber: 0".into());
+    line("*cupsFilter: \"application/vnd.cups-raster 0 rastertobrotherql\"".into());
+    if let Some(path) = labels_file {
+        line(format!("{}: \"{}\"", LABELS_KEYWORD, path));
+    }
+
+    let default = labels
+        .iter()
+        .find(|label| label.tape_size.0 == DEFAULT_WIDTH_MM && !label.is_die_cut())
+        .or(labels.first())
+        .map(page_size_name)
+        .unwrap_or_default();
+    for (keyword, text) in [("PageSize", "Media Size"), ("PageRegion", "Media Region")] {
+        line(String::new());
+        line(format!("*OpenUI *{}/{}: PickOne", keyword, text));
+        line(format!("*OrderDependency: 10 AnySetup *{}", keyword));
+        line(format!("*Default{}: {}", keyword, default));
+        for label in labels {
+            let (width, length) = size(label);
+            line(format!(
+                "*{} {}/{}: \"<</PageSize[{:.2} {:.2}]/ImagingBBox null>>setpagedevice\"",
+                keyword,
+                page_size_name(label),
+                description(label),
+                width,
+                length
+            ));
+        }
+        line(format!("*CloseUI: *{}", keyword));
+    }
+
+    line(String::new());
+    line(format!("*DefaultImageableArea: {}", default));
+    for label in labels {
+        let (width, length) = size(label);
+        let dots = |dots: u32| dots as f64 * 72.0 / DPI;
+        let left = dots(label.dots.0 - label.dots_printable.0) / 2.0;
+        let bottom = if label.is_die_cut() {
+            dots(label.dots.1 - label.dots_printable.1) / 2.0
+        } else {
+            0.0
+        };
+        line(format!(
+            "*ImageableArea {}/{}: \"{:.2} {:.2} {:.2} {:.2}\"",
+            page_size_name(label),
+            description(label),
+            left,
+            bottom,
+            width - left,
+            length - bottom
+        ));
+    }
+    line(format!("*DefaultPaperDimension: {}", default));
+    for label in labels {
+        let (width, length) = size(label);
+        line(format!(
+            "*PaperDimension {}/{}: \"{:.2} {:.2}\"",
+            page_size_name(label),
+            description(label),
+            width,
+            length
+        ));
+    }
+
+    let max_width = labels
+        .iter()
+        .map(|label| points(label.tape_size.0 as f64))
+        .fold(0.0, f64::max);
+    line(String::new());
+    line("*VariablePaperSize: True".into());
+    line(format!("*MaxMediaWidth: \"{:.2}\"", max_width));
+    line(format!("*MaxMediaHeight: \"{:.2}\"", points(MAX_LENGTH_MM)));
+    line("*HWMargins: 0 0 0 0".into());
+    line("*CustomPageSize True: \"pop pop pop <</PageSize[5 -2 roll]/ImagingBBox null>>setpagedevice\"".into());
+    line(format!("*ParamCustomPageSize Width: 1 points 36 {:.2}", max_width));
+    line(format!("*ParamCustomPageSize Height: 2 points 36 {:.2}", points(MAX_LENGTH_MM)));
+    line("*ParamCustomPageSize WidthOffset: 3 points 0 0".into());
+    line("*ParamCustomPageSize HeightOffset: 4 points 0 0".into());
+    line("*ParamCustomPageSize Orientation: 5 int 0 0".into());
+
+    line(String::new());
+    line("*OpenUI *Resolution/Resolution: PickOne".into());
+    line("*OrderDependency: 10 AnySetup *Resolution".into());
+    line("*DefaultResolution: 300dpi".into());
+    let mut resolutions = vec![("300dpi", "300 dpi", 300)];
+    if model.supports_high_resolution() {
+        resolutions.push(("300x600dpi", "300 x 600 dpi", 600));
+    }
+    for (keyword, text, vertical) in resolutions {
+        line(format!(
+            "*Resolution {}/{}: \"<</HWResolution[300 {}]/cupsBitsPerColor 1/cupsColorOrder 0/cupsColorSpace 3>>setpagedevice\"",
+            keyword, text, vertical
+        ));
+    }
+    line("*CloseUI: *Resolution".into());
+
+    line(String::new());
+    line("*OpenUI *CutMedia/Cut: PickOne".into());
+    line("*OrderDependency: 10 AnySetup *CutMedia".into());
+    line("*DefaultCutMedia: EveryLabel".into());
+    line("*CutMedia EveryLabel/After every label: \"<</CutMedia 4>>setpagedevice\"".into());
+    line("*CutMedia EndOfJob/At the end of the job: \"<</CutMedia 2>>setpagedevice\"".into());
+    line("*CutMedia Never/Never: \"<</CutMedia 0>>setpagedevice\"".into());
+    line("*CloseUI: *CutMedia".into());
+    ppd
+}
+
+/// Size of the page for a label in points
+fn size(label: &Label) -> (f64, f64) {
+    let length = if label.is_die_cut() {
+        label.tape_size.1 as f64
+    } else {
+        CONTINUOUS_LENGTH_MM
+    };
+    (points(label.tape_size.0 as f64), points(length))
+}
+
+fn description(label: &Label) -> String {
+    if label.is_die_cut() {
+        format!("{} x {} mm", label.tape_size.0, label.tape_size.1)
+    } else {
+        format!("{} mm continuous", label.tape_size.0)
+    }
+}
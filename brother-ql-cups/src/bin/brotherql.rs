@@ -0,0 +1,136 @@
+//! CUPS backend sending command streams to Brother QL printers attached over USB
+//!
+//! Without arguments, lists the attached printers with their device URIs, such as
+//! `brotherql://Brother/QL-700?serial=000A1B2C3D4E`, the manufacturer, model and serial number being
+//! percent-encoded. With the arguments of a job,
+//! `brotherql JOB USER TITLE COPIES OPTIONS [FILE]`, sends the command stream written by
+//! `rastertobrotherql` to the printer named by `DEVICE_URI` and waits until every label is printed.
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::process;
+
+use brother_ql_cups::{percent_decode, percent_encode};
+use brother_ql_rs::printer::decoder::{check_job, decode};
+use brother_ql_rs::printer::{printers, Error, ErrorKind, ThermalPrinter};
+use rusb::GlobalContext;
+
+const SCHEME: &str = "brotherql";
+
+// Exit codes understood by CUPS
+const BACKEND_OK: i32 = 0;
+const BACKEND_FAILED: i32 = 1;
+const BACKEND_RETRY: i32 = 6;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let code = match args.len() {
+        1 => list(),
+        6 | 7 => {
+            let uri = env::var("DEVICE_URI").unwrap_or_else(|_| args[0].clone());
+            print(&uri, args.get(6))
+        }
+        _ => {
+            eprintln!("Usage: {} JOB USER TITLE COPIES OPTIONS [FILE]", SCHEME);
+            BACKEND_FAILED
+        }
+    };
+    process::exit(code);
+}
+
+/// Opens all connected printers without resetting them, which would interrupt their jobs.
+fn open_printers() -> Vec<ThermalPrinter<GlobalContext>> {
+    printers()
+        .into_iter()
+        .filter_map(|device| match ThermalPrinter::open(device) {
+            Ok(printer) => Some(printer),
+            Err(error) => {
+                eprintln!("DEBUG: Skipping printer: {}", error);
+                None
+            }
+        })
+        .collect()
+}
+
+fn list() -> i32 {
+    for printer in open_printers() {
+        let model = format!("{} {}", printer.manufacturer, printer.model);
+        println!(
+            "direct {}://{}/{}?serial={} \"{}\" \"{} ({})\" \"MFG:{};MDL:{};SN:{};\" \"\"",
+            SCHEME,
+            percent_encode(&printer.manufacturer),
+            percent_encode(&printer.model),
+            percent_encode(&printer.serial_number),
+            model,
+            model,
+            printer.serial_number,
+            printer.manufacturer,
+            printer.model,
+            printer.serial_number
+        );
+    }
+    BACKEND_OK
+}
+
+fn print(uri: &str, file: Option<&String>) -> i32 {
+    let serial = match uri
+        .split_once("?serial=")
+        .and_then(|(_, serial)| percent_decode(serial))
+    {
+        Some(serial) => serial,
+        None => {
+            eprintln!("ERROR: Device URI {} doesn't name a printer", uri);
+            return BACKEND_FAILED;
+        }
+    };
+    let data = match file {
+        Some(path) => fs::read(path),
+        None => {
+            let mut data = Vec::new();
+            io::stdin().read_to_end(&mut data).map(|_| data)
+        }
+    };
+    let data = match data {
+        Ok(data) => data,
+        Err(error) => {
+            eprintln!("ERROR: Can't read the job: {}", error);
+            return BACKEND_FAILED;
+        }
+    };
+    let pages = match decode(&data).and_then(|instructions| check_job(&instructions)) {
+        Ok(pages) => pages,
+        Err(error) => {
+            eprintln!("ERROR: Invalid job: {}", error);
+            return BACKEND_FAILED;
+        }
+    };
+
+    let printer = match open_printers()
+        .into_iter()
+        .find(|printer| printer.serial_number == serial)
+    {
+        Some(printer) => printer,
+        None => {
+            eprintln!("INFO: Waiting for printer {} to be connected", serial);
+            return BACKEND_RETRY;
+        }
+    };
+    eprintln!("INFO: Printing {} labels on {:?}", pages, printer);
+    let result = printer
+        .reset()
+        .and_then(|_| printer.get_status())
+        .and_then(|status| status.into_result())
+        .and_then(|_| printer.write_raw(&data))
+        .and_then(|_| printer.wait_for_completion(pages));
+    match result {
+        Ok(_) => BACKEND_OK,
+        Err(Error(ErrorKind::Disconnected, _)) => {
+            eprintln!("ERROR: The printer was disconnected");
+            BACKEND_RETRY
+        }
+        Err(error) => {
+            eprintln!("ERROR: {}", error);
+            BACKEND_FAILED
+        }
+    }
+}
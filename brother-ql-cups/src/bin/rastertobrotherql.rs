@@ -0,0 +1,129 @@
+//! CUPS filter turning CUPS raster pages into the command stream of Brother QL printers
+//!
+//! Usage: `rastertobrotherql JOB USER TITLE COPIES OPTIONS [FILE]`, reading the raster data from
+//! `FILE` or stdin and writing the command stream to stdout. The printer model is read from the PPD
+//! named by the `PPD` environment variable, and the label from the page size of the first page.
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::process;
+
+use brother_ql_cups::{label_for_page, load_ppd};
+use brother_ql_rs::printer::constants::Label;
+use brother_ql_rs::printer::encoder::encode_print_file;
+use brother_ql_rs::printer::job::{Cut, PrintJob};
+use brother_ql_rs::printer::model::PrinterModel;
+use brother_ql_rs::printer::raster::{self, CutMedia, RasterPage};
+use brother_ql_rs::printer::setting::Resolution;
+use brother_ql_rs::printer::Result;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 6 || args.len() > 7 {
+        eprintln!("Usage: rastertobrotherql JOB USER TITLE COPIES OPTIONS [FILE]");
+        process::exit(1);
+    }
+    let copies = args[4].parse::<usize>().unwrap_or(1).max(1);
+    if let Err(error) = filter(copies, args.get(6)) {
+        eprintln!("ERROR: {}", error);
+        process::exit(1);
+    }
+}
+
+fn filter(copies: usize, file: Option<&String>) -> Result<()> {
+    let model = match env::var("PPD") {
+        Ok(path) => load_ppd(&fs::read_to_string(path)?)?,
+        Err(_) => PrinterModel::Unknown,
+    };
+    let input: Box<dyn Read> = match file {
+        Some(path) => Box::new(File::open(path)?),
+        None => Box::new(io::stdin()),
+    };
+    let pages = raster::read_pages(input)?;
+    let first = match pages.first() {
+        Some(page) => page,
+        None => return Err("The job has no pages".into()),
+    };
+    let label = match label_for_page(&first.page_size_name, first.page_size) {
+        Some(label) => label,
+        None => return Err(format!("No label matches the page size {}", first.page_size_name).into()),
+    };
+
+    let pages: Vec<&RasterPage> = std::iter::repeat_n(&pages, copies).flatten().collect();
+    let mut jobs = Vec::new();
+    for (index, page) in pages.iter().enumerate() {
+        let last = index == pages.len() - 1;
+        jobs.push(job(page, &label, last)?);
+    }
+
+    let file = encode_print_file(model, &label, &jobs)?;
+    for page in 1..=jobs.len() {
+        eprintln!("PAGE: {} 1", page);
+    }
+    io::stdout().write_all(&file)?;
+    io::stdout().flush()?;
+    Ok(())
+}
+
+/// Creates the job for a page, cutting after it as requested by the `CutMedia` setting of the page.
+fn job(page: &RasterPage, label: &Label, last: bool) -> Result<PrintJob> {
+    let resolution = match page.resolution {
+        (300, 300) => Resolution::Normal,
+        (300, 600) => Resolution::High,
+        (x, y) => return Err(format!("Raster resolution of {}x{}dpi is not supported", x, y).into()),
+    };
+    let mut job = PrintJob::from_label_page(&page.pixels, page.width, label, resolution)?;
+    job.cut = match page.cut_media {
+        CutMedia::Never => Cut::Never,
+        CutMedia::AfterPage => Cut::AtEnd,
+        _ if last => Cut::AtEnd,
+        _ => Cut::Never,
+    };
+    Ok(job)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use brother_ql_rs::printer::constants::label_data;
+
+    fn page(resolution: (u32, u32), cut_media: CutMedia) -> RasterPage {
+        RasterPage {
+            width: 696,
+            height: 2,
+            resolution,
+            page_size: (176, 0),
+            page_size_name: "62mm".to_string(),
+            copies: 1,
+            cut_media,
+            pixels: vec![0xFF; 696 * 2],
+        }
+    }
+
+    #[test]
+    fn cuts_as_requested_by_the_page() {
+        let label = label_data(62, None).unwrap();
+        let cut = |cut_media, last| job(&page((300, 300), cut_media), &label, last).unwrap().cut;
+        assert_eq!(cut(CutMedia::Never, true), Cut::Never);
+        assert_eq!(cut(CutMedia::AfterPage, false), Cut::AtEnd);
+        // Cut after the document, job or set: only after the last page
+        for cut_media in [
+            CutMedia::AfterDocument,
+            CutMedia::AfterJob,
+            CutMedia::AfterSet,
+        ] {
+            assert_eq!(cut(cut_media, false), Cut::Never);
+            assert_eq!(cut(cut_media, true), Cut::AtEnd);
+        }
+    }
+
+    #[test]
+    fn prints_supported_resolutions() {
+        let label = label_data(62, None).unwrap();
+        let resolution =
+            |dpi| job(&page(dpi, CutMedia::Never), &label, true).map(|job| job.resolution);
+        assert_eq!(resolution((300, 300)).unwrap(), Resolution::Normal);
+        assert_eq!(resolution((300, 600)).unwrap(), Resolution::High);
+        assert!(resolution((600, 600)).is_err());
+    }
+}
@@ -0,0 +1,165 @@
+//! Printing to Brother QL printers through CUPS
+//!
+//! * `brother-ql-ppd` writes a PPD file for a printer model, listing every known label as a page size
+//! * `rastertobrotherql` is the filter turning the CUPS raster pages of a job into the printer's
+//!   command stream
+//! * `brotherql` is the backend finding printers on USB and sending command streams to them
+//!
+//! The page sizes of the PPD are named after the labels, `29x90mm` for die-cut labels and `62mm` for
+//! continuous tape, so the filter can tell which label a page was laid out for.
+use brother_ql_rs::printer::constants::{known_labels, Label};
+use brother_ql_rs::printer::model::PrinterModel;
+use brother_ql_rs::printer::registry;
+
+/// PPD keyword naming a label file to load, see `registry::load_labels`
+pub const LABELS_KEYWORD: &str = "*BrotherQLLabels";
+
+/// Name of the page size for a label
+pub fn page_size_name(label: &Label) -> String {
+    if label.is_die_cut() {
+        format!("{}x{}mm", label.tape_size.0, label.tape_size.1)
+    } else {
+        format!("{}mm", label.tape_size.0)
+    }
+}
+
+/// Converts millimeters to points, the unit of PPD files and CUPS raster page sizes.
+pub fn points(mm: f64) -> f64 {
+    mm * 72.0 / 25.4
+}
+
+/// Finds the label a page was laid out for, by its page size name or else by its size in points.
+///
+/// Custom page sizes are printed on continuous tape of the same width.
+pub fn label_for_page(name: &str, size: (u32, u32)) -> Option<Label> {
+    let labels = known_labels();
+    if let Some(label) = labels.iter().find(|label| page_size_name(label) == name) {
+        return Some(*label);
+    }
+    let mm = |points: u32| (points as f64 * 25.4 / 72.0).round() as u32;
+    let (width, length) = (mm(size.0), mm(size.1));
+    labels
+        .iter()
+        .find(|label| label.tape_size.0 == width && label.tape_size.1 == length)
+        .or_else(|| {
+            labels
+                .iter()
+                .find(|label| label.tape_size.0 == width && !label.is_die_cut())
+        })
+        .copied()
+}
+
+/// Percent-encodes a value for a device URI, keeping only the characters that never need encoding.
+pub fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Decodes a value encoded with `percent_encode`, or `None` if it isn't valid.
+pub fn percent_decode(value: &str) -> Option<String> {
+    let mut decoded = Vec::with_capacity(value.len());
+    let mut bytes = value.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let hex = [bytes.next()?, bytes.next()?];
+            decoded.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            decoded.push(byte);
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// Reads the printer model and any label file from a PPD file written by `brother-ql-ppd`,
+/// registering the labels.
+pub fn load_ppd(ppd: &str) -> brother_ql_rs::printer::Result<PrinterModel> {
+    let mut model = PrinterModel::Unknown;
+    for line in ppd.lines() {
+        if let Some(value) = line.strip_prefix("*ModelName:") {
+            let name = value.trim().trim_matches('"');
+            model = PrinterModel::from_name(name.rsplit(' ').next().unwrap_or(name));
+        } else if let Some(value) = line.strip_prefix(LABELS_KEYWORD).and_then(|rest| rest.strip_prefix(':')) {
+            registry::load_labels(value.trim().trim_matches('"'))?;
+        }
+    }
+    Ok(model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn finds_labels_by_page_size_name() {
+        let label = label_for_page("62mm", (0, 0)).unwrap();
+        assert_eq!((label.tape_size.0, label.tape_size.1), (62, 0));
+        let label = label_for_page("29x90mm", (0, 0)).unwrap();
+        assert_eq!((label.tape_size.0, label.tape_size.1), (29, 90));
+    }
+
+    #[test]
+    fn finds_labels_by_page_size() {
+        let size =
+            |width: f64, length: f64| (points(width).round() as u32, points(length).round() as u32);
+        let label = label_for_page("Custom", size(29.0, 90.0)).unwrap();
+        assert_eq!((label.tape_size.0, label.tape_size.1), (29, 90));
+        // Custom lengths are printed on continuous tape
+        let label = label_for_page("Custom", size(62.0, 123.0)).unwrap();
+        assert_eq!((label.tape_size.0, label.tape_size.1), (62, 0));
+        assert!(label_for_page("Custom", size(5.0, 10.0)).is_none());
+    }
+
+    #[test]
+    fn reads_model_from_ppd() {
+        let ppd = "*PPD-Adobe: \"4.3\"\n*ModelName: \"Brother QL-700\"\n*NickName: \"Brother QL-700, brother-ql-rs\"\n";
+        assert_eq!(load_ppd(ppd).unwrap(), PrinterModel::QL700);
+        assert_eq!(
+            load_ppd("*ModelName: \"Brother QL-1060N\"").unwrap(),
+            PrinterModel::QL1060N
+        );
+        assert_eq!(
+            load_ppd("*ModelName: \"QL-9999\"").unwrap(),
+            PrinterModel::Unknown
+        );
+        assert_eq!(load_ppd("").unwrap(), PrinterModel::Unknown);
+    }
+
+    #[test]
+    fn loads_labels_named_in_ppd() {
+        let path =
+            std::env::temp_dir().join(format!("brother-ql-cups-labels-{}", std::process::id()));
+        fs::write(&path, "61 31 732 380 696 310 12 0\n").unwrap();
+        let ppd = format!(
+            "*ModelName: \"Brother QL-570\"\n{}: \"{}\"\n",
+            LABELS_KEYWORD,
+            path.display()
+        );
+        assert_eq!(load_ppd(&ppd).unwrap(), PrinterModel::QL570);
+        assert!(label_for_page("61x31mm", (0, 0)).is_some());
+        fs::remove_file(&path).unwrap();
+
+        let missing = format!("{}: \"{}\"\n", LABELS_KEYWORD, path.display());
+        assert!(load_ppd(&missing).is_err());
+    }
+
+    #[test]
+    fn percent_encodes_uri_values() {
+        let encoded = percent_encode("Brother Industries, Ltd./QL-700?");
+        assert_eq!(encoded, "Brother%20Industries%2C%20Ltd.%2FQL-700%3F");
+        assert_eq!(
+            percent_decode(&encoded).unwrap(),
+            "Brother Industries, Ltd./QL-700?"
+        );
+        assert_eq!(percent_decode("000A1B2C").unwrap(), "000A1B2C");
+        assert!(percent_decode("%2").is_none());
+        assert!(percent_decode("%zz").is_none());
+    }
+}
@@ -0,0 +1,82 @@
+//! Print jobs built from numpy arrays and Pillow images
+use brother_ql_rs::printer::job::{self, Cut};
+use brother_ql_rs::printer::setting::Resolution;
+use numpy::PyReadonlyArray2;
+use pyo3::exceptions::PyTypeError;
+use pyo3::prelude::*;
+
+use crate::label::Label;
+use crate::printer_error;
+
+/// One page to print
+#[pyclass(frozen, module = "brother_ql_rs")]
+pub struct PrintJob {
+    pub job: job::PrintJob,
+}
+
+#[pymethods]
+impl PrintJob {
+    /// Creates a job printing an image, either a 2-D `uint8` numpy array of grayscale pixels or a
+    /// Pillow image. Pixels darker than mid-gray are printed.
+    ///
+    /// By default the image is placed on the printable area of `label` and mustn't be wider than it.
+    /// With `full_page`, the image covers the whole label and is cropped to its printable area, as
+    /// pages from desktop applications are.
+    ///
+    /// `high_resolution` prints at 300x600 dpi. Images have square pixels, so every row is printed
+    /// twice, except for `full_page` images, which are already laid out at 300x600 dpi.
+    #[staticmethod]
+    #[pyo3(signature = (image, label, full_page = false, high_resolution = false, cut = true))]
+    fn from_image(
+        image: &Bound<'_, PyAny>,
+        label: &Label,
+        full_page: bool,
+        high_resolution: bool,
+        cut: bool,
+    ) -> PyResult<PrintJob> {
+        let (pixels, width) = grayscale(image)?;
+        let resolution = if high_resolution {
+            Resolution::High
+        } else {
+            Resolution::Normal
+        };
+        let mut job = if full_page {
+            job::PrintJob::from_label_page(&pixels, width, &label.label, resolution)
+        } else {
+            job::PrintJob::from_grayscale(&pixels, width, &label.label)
+                .map(|job| job.with_resolution(resolution))
+        }
+        .map_err(printer_error)?;
+        if !cut {
+            job.cut = Cut::Never;
+        }
+        Ok(PrintJob { job })
+    }
+
+    /// Number of raster lines, the length of the page in dots
+    #[getter]
+    fn raster_lines(&self) -> usize {
+        self.job.raster_lines.len()
+    }
+
+    /// Checks that the job can be printed on `label`, raising `PrinterError` if it can't.
+    fn validate(&self, label: &Label) -> PyResult<()> {
+        self.job.validate(&label.label).map_err(printer_error)
+    }
+}
+
+/// Reads the grayscale pixels of an image, returning them with the width of the image.
+fn grayscale(image: &Bound<'_, PyAny>) -> PyResult<(Vec<u8>, usize)> {
+    // Pillow images
+    if image.hasattr("convert")? && image.hasattr("tobytes")? {
+        let image = image.call_method1("convert", ("L",))?;
+        let width = image.getattr("width")?.extract()?;
+        let pixels = image.call_method0("tobytes")?.extract()?;
+        return Ok((pixels, width));
+    }
+    if let Ok(array) = image.extract::<PyReadonlyArray2<u8>>() {
+        let array = array.as_array();
+        return Ok((array.iter().copied().collect(), array.ncols()));
+    }
+    Err(PyTypeError::new_err("Expected a 2-D uint8 numpy array or a Pillow image"))
+}
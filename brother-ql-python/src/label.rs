@@ -0,0 +1,126 @@
+//! Label media and the catalogue of labels sold by Brother
+use brother_ql_rs::printer::catalogue::{self, CatalogueEntry, Roll};
+use brother_ql_rs::printer::constants;
+use pyo3::prelude::*;
+
+/// A label size, along with its catalogue entry if it is sold by Brother
+#[pyclass(frozen, module = "brother_ql_rs")]
+#[derive(Clone)]
+pub struct Label {
+    pub label: constants::Label,
+    entry: Option<&'static CatalogueEntry>,
+}
+
+impl Label {
+    pub fn new(label: constants::Label) -> Label {
+        let entry = catalogue::find_by_size(label.tape_size.0, label.tape_size.1)
+            .filter(|entry| entry.label == label);
+        Label { label, entry }
+    }
+}
+
+impl From<&'static CatalogueEntry> for Label {
+    fn from(entry: &'static CatalogueEntry) -> Label {
+        Label {
+            label: entry.label,
+            entry: Some(entry),
+        }
+    }
+}
+
+#[pymethods]
+impl Label {
+    /// Catalogue name such as `29x90` or `62`, or the size for labels not in the catalogue
+    #[getter]
+    fn name(&self) -> String {
+        match self.entry {
+            Some(entry) => entry.name.to_string(),
+            None if self.label.is_die_cut() => {
+                format!("{}x{}", self.label.tape_size.0, self.label.tape_size.1)
+            }
+            None => self.label.tape_size.0.to_string(),
+        }
+    }
+
+    #[getter]
+    fn description(&self) -> Option<&'static str> {
+        self.entry.map(|entry| entry.description)
+    }
+
+    /// DK part numbers of the rolls with this label
+    #[getter]
+    fn part_numbers(&self) -> Vec<&'static str> {
+        self.entry.map_or(Vec::new(), |entry| entry.part_numbers.to_vec())
+    }
+
+    /// Tape width in mm
+    #[getter]
+    fn width(&self) -> u32 {
+        self.label.tape_size.0
+    }
+
+    /// Label length in mm, `0` for continuous tape
+    #[getter]
+    fn length(&self) -> u32 {
+        self.label.tape_size.1
+    }
+
+    #[getter]
+    fn die_cut(&self) -> bool {
+        self.label.is_die_cut()
+    }
+
+    /// Width of the printable area in dots, the widest image that can be printed
+    #[getter]
+    fn printable_width(&self) -> u32 {
+        self.label.dots_printable.0
+    }
+
+    /// Length of the printable area in dots, `0` for continuous tape
+    #[getter]
+    fn printable_length(&self) -> u32 {
+        self.label.dots_printable.1
+    }
+
+    /// Number of labels on a full roll of die-cut labels, if known
+    #[getter]
+    fn roll_labels(&self) -> Option<u32> {
+        match self.entry?.roll? {
+            Roll::Labels(labels) => Some(labels),
+            Roll::Millimeters(_) => None,
+        }
+    }
+
+    /// Length of a full roll of continuous tape in mm, if known
+    #[getter]
+    fn roll_millimeters(&self) -> Option<u32> {
+        match self.entry?.roll? {
+            Roll::Millimeters(millimeters) => Some(millimeters),
+            Roll::Labels(_) => None,
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Label('{}')", self.name())
+    }
+}
+
+/// Finds a label by its name (e.g. `29x90`) or DK part number (e.g. `DK-11201`).
+#[pyfunction]
+pub fn find_label(name: &str) -> Option<Label> {
+    catalogue::find(name).map(Label::from)
+}
+
+/// Finds a label by its size in mm, using a length of `0` for continuous tape, including labels
+/// registered with the Rust library.
+#[pyfunction]
+#[pyo3(signature = (width, length = 0))]
+pub fn label_for_size(width: u8, length: u8) -> Option<Label> {
+    constants::label_data(width, Some(length).filter(|&length| length != 0)).map(Label::new)
+}
+
+/// Every label in the catalogue
+#[pyfunction]
+pub fn labels() -> Vec<Label> {
+    catalogue::catalogue().iter().map(Label::from).collect()
+}
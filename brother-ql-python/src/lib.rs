@@ -0,0 +1,65 @@
+//! Python bindings of brother-ql-rs
+//!
+//! Built into the `brother_ql_rs` Python module with `maturin build --release`, so Python tools print
+//! exactly what our Rust services print:
+//!
+//! ```python
+//! import brother_ql_rs
+//! from PIL import Image
+//!
+//! printer = brother_ql_rs.printers()[0]
+//! label = printer.current_label()
+//! job = brother_ql_rs.PrintJob.from_image(Image.open("label.png"), label, full_page=True)
+//! printer.print([job])
+//! ```
+//!
+//! Errors of the printer or of USB communication are raised as `PrinterError`.
+use brother_ql_rs::printer::{self, ThermalPrinter};
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+
+mod job;
+mod label;
+mod printer_class;
+
+use job::PrintJob;
+use label::Label;
+use printer_class::{Printer, Status};
+
+create_exception!(
+    brother_ql_rs,
+    PrinterError,
+    PyException,
+    "Raised when printing fails or the printer reports an error."
+);
+
+pub(crate) fn printer_error(error: printer::Error) -> PyErr {
+    PrinterError::new_err(error.to_string())
+}
+
+/// Opens every attached printer that is ready to be printed to, skipping printers that can't be
+/// opened. Printers aren't reset, so listing them doesn't abort jobs in progress.
+#[pyfunction]
+fn printers() -> Vec<Printer> {
+    printer::printers()
+        .into_iter()
+        .filter_map(|device| ThermalPrinter::open(device).ok())
+        .map(Printer::new)
+        .collect()
+}
+
+#[pymodule]
+#[pyo3(name = "brother_ql_rs")]
+fn python_module(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add("PrinterError", module.py().get_type::<PrinterError>())?;
+    module.add_class::<Label>()?;
+    module.add_class::<PrintJob>()?;
+    module.add_class::<Printer>()?;
+    module.add_class::<Status>()?;
+    module.add_function(wrap_pyfunction!(printers, module)?)?;
+    module.add_function(wrap_pyfunction!(label::find_label, module)?)?;
+    module.add_function(wrap_pyfunction!(label::label_for_size, module)?)?;
+    module.add_function(wrap_pyfunction!(label::labels, module)?)?;
+    Ok(())
+}
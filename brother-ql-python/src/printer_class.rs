@@ -0,0 +1,122 @@
+//! Printers attached over USB
+use brother_ql_rs::printer::{status, ThermalPrinter};
+use pyo3::prelude::*;
+use rusb::GlobalContext;
+
+use crate::job::PrintJob;
+use crate::label::Label;
+use crate::printer_error;
+
+/// A printer attached over USB, see `printers()`
+#[pyclass(frozen, module = "brother_ql_rs")]
+pub struct Printer {
+    printer: ThermalPrinter<GlobalContext>,
+}
+
+impl Printer {
+    pub fn new(printer: ThermalPrinter<GlobalContext>) -> Printer {
+        Printer { printer }
+    }
+}
+
+#[pymethods]
+impl Printer {
+    #[getter]
+    fn manufacturer(&self) -> &str {
+        &self.printer.manufacturer
+    }
+
+    #[getter]
+    fn model(&self) -> &str {
+        &self.printer.model
+    }
+
+    #[getter]
+    fn serial_number(&self) -> &str {
+        &self.printer.serial_number
+    }
+
+    /// Whether the printer is still attached
+    #[getter]
+    fn connected(&self) -> bool {
+        self.printer.is_connected()
+    }
+
+    /// Asks the printer for its status.
+    fn status(&self, py: Python<'_>) -> PyResult<Status> {
+        let status = py.detach(|| self.printer.get_status());
+        status.map(Status::from).map_err(printer_error)
+    }
+
+    /// The label loaded into the printer
+    fn current_label(&self, py: Python<'_>) -> PyResult<Label> {
+        let label = py.detach(|| self.printer.current_label());
+        label.map(Label::new).map_err(printer_error)
+    }
+
+    /// Prints pages, waiting until every page was printed unless `wait` is false.
+    #[pyo3(signature = (jobs, wait = true))]
+    fn print(&self, py: Python<'_>, jobs: Vec<PyRef<'_, PrintJob>>, wait: bool) -> PyResult<()> {
+        let jobs: Vec<_> = jobs.iter().map(|job| job.job.clone()).collect();
+        py.detach(|| {
+            if wait {
+                self.printer.print_pages_blocking(&jobs)
+            } else {
+                self.printer.print_pages(&jobs).map(|_| ())
+            }
+        })
+        .map_err(printer_error)
+    }
+
+    /// Clears anything the printer has received so far, aborting any job in progress.
+    fn reset(&self, py: Python<'_>) -> PyResult<()> {
+        py.detach(|| self.printer.reset()).map_err(printer_error)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("<Printer {:?}>", self.printer)
+    }
+}
+
+/// Status reported by a printer
+#[pyclass(frozen, get_all, module = "brother_ql_rs")]
+pub struct Status {
+    pub model: String,
+    pub status_type: String,
+    /// Errors the printer reports, empty if there are none
+    pub errors: Vec<&'static str>,
+    pub media_type: String,
+    /// Size of the loaded media in mm, with a length of `0` for continuous tape
+    pub media_width: u8,
+    pub media_length: u8,
+}
+
+impl From<status::Response> for Status {
+    fn from(response: status::Response) -> Status {
+        Status {
+            model: format!("{:?}", response.model),
+            status_type: format!("{:?}", response.status_type),
+            errors: response.errors,
+            media_type: format!("{:?}", response.media.media_type),
+            media_width: response.media.width,
+            media_length: response.media.length,
+        }
+    }
+}
+
+#[pymethods]
+impl Status {
+    /// The loaded label, if it is a known one
+    #[getter]
+    fn label(&self) -> Option<Label> {
+        let length = Some(self.media_length).filter(|&length| length != 0);
+        brother_ql_rs::printer::constants::label_data(self.media_width, length).map(Label::new)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "<Status {} {}x{}mm {}>",
+            self.status_type, self.media_width, self.media_length, self.model
+        )
+    }
+}
@@ -3,30 +3,67 @@
 //! Based on the published [Brother QL Series Command Reference](https://download.brother.com/welcome/docp000678/cv_qlseries_eng_raster_600.pdf)
 
 use crate::printer::command::Command;
-use crate::printer::command::Command::{GetStatus, StartPrint};
+use crate::printer::config::PrinterConfig;
+use crate::printer::command::Command::{GetStatus, Initialize, Invalidate};
 use crate::printer::constants::{MAX_PIXEL_WIDTH, RASTER_LINE_LENGTH};
 use crate::printer::job::PrintJob;
-use crate::printer::media_type::MediaType;
-use crate::printer::model::PrinterModel;
-use crate::printer::setting::PrinterSetting::{
-    HighResMode, MirrorOrCut, NormalResMode, SwitchToRasterMode,
-};
-use crate::printer::setting::{PrinterSetting, Resolution};
+use crate::printer::setting::PrinterSetting::{PowerOnWhenConnected, SleepTimer};
+use crate::printer::setting::{DeviceSettings, PrinterSetting, SleepTimerValue};
 use crate::printer::status_type::StatusType;
+use log::{debug, trace, warn};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
 use std::thread;
-use std::time::Duration;
+use std::time::{Instant, SystemTime};
 
+#[cfg(feature = "async")]
+pub mod asynchronous;
+#[cfg(feature = "batch")]
+pub mod batch;
+pub mod catalogue;
 mod command;
+pub mod config;
 pub mod constants;
+pub mod decoder;
+pub mod encoder;
+pub mod hotplug;
 pub mod job;
+#[cfg(feature = "journal")]
+pub mod journal;
 mod media_type;
-mod model;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod model;
+pub mod pool;
+pub mod preview;
+pub mod raster;
+pub mod registry;
 pub mod setting;
+pub mod spooler;
 mod status_type;
+#[cfg(test)]
+mod test_support;
+#[cfg(feature = "usage")]
+pub mod usage;
 
 error_chain! {
     foreign_links {
         USB(rusb::Error);
+        Io(std::io::Error);
+        Csv(csv::Error) #[cfg(feature = "batch")];
+        Png(png::EncodingError) #[cfg(feature = "preview")];
+        Json(serde_json::Error) #[cfg(any(feature = "journal", feature = "usage"))];
+    }
+
+    errors {
+        Disconnected {
+            description("printer disconnected")
+            display("The printer was disconnected")
+        }
+        Printer(errors: Vec<&'static str>) {
+            description("printer reported an error")
+            display("The printer reported an error: {}", errors.join(", "))
+        }
     }
 }
 
@@ -45,49 +82,238 @@ pub mod status {
     use crate::printer::status_type::StatusType;
 
     #[derive(Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize))]
     pub struct Media {
         pub media_type: MediaType,
         pub width: u8,  // unit: mm
         pub length: u8, // unit: mm
     }
     impl Media {
+        /// The media a printer reports with the given label loaded
+        pub fn from_label(label: &Label) -> Media {
+            let media_type = if label.is_die_cut() {
+                MediaType::DieCutLabels
+            } else {
+                MediaType::ContinuousTape
+            };
+            Media {
+                media_type,
+                width: label.tape_size.0 as u8,
+                length: label.tape_size.1 as u8,
+            }
+        }
+
+        /// Panics if the loaded media isn't a known label.
+        #[deprecated(note = "use `label()`, which returns `None` for unknown media instead of panicking")]
         pub fn to_label(&self) -> Label {
+            self.label().expect("Printer reported invalid label dimensions")
+        }
+
+        /// Looks up the label definition matching the loaded media, if it is a known one.
+        pub fn label(&self) -> Option<Label> {
             let length = if self.length == 0 {
                 None
             } else {
                 Some(self.length)
             };
-            label_data(self.width, length).expect("Printer reported invalid label dimensions")
+            label_data(self.width, length)
         }
     }
 
     #[derive(Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize))]
     pub struct Response {
         pub model: PrinterModel,
         pub status_type: StatusType,
         pub errors: Vec<&'static str>,
         pub media: Media,
     }
+
+    /// Size of a status message in bytes
+    pub const RESPONSE_SIZE: usize = 32;
+
+    impl Response {
+        /// Parses a status message received from the printer.
+        pub fn from_bytes(response: &[u8; RESPONSE_SIZE]) -> crate::printer::Result<Response> {
+            if response[0] != 0x80 {
+                return Err("Invalid response received from printer".into());
+            }
+
+            let model = PrinterModel::from_byte(response[4]);
+
+            let mut errors = Vec::new();
+
+            fn error_if(byte: u8, flag: u8, message: &'static str, errors: &mut Vec<&'static str>) {
+                if byte & flag != 0 {
+                    errors.push(message);
+                }
+            }
+            error_if(response[8], 0x01, "No media when printing", &mut errors);
+            error_if(response[8], 0x02, "End of media", &mut errors);
+            error_if(response[8], 0x04, "Tape cutter jam", &mut errors);
+            error_if(response[8], 0x10, "Main unit in use", &mut errors);
+            error_if(response[8], 0x80, "Fan doesn't work", &mut errors);
+            error_if(response[9], 0x04, "Transmission error", &mut errors);
+            error_if(response[9], 0x10, "Cover open", &mut errors);
+            error_if(response[9], 0x40, "Cannot feed", &mut errors);
+            error_if(response[9], 0x80, "System error", &mut errors);
+
+            let width = response[10];
+            let length = response[17];
+
+            let media_type = MediaType::from_byte(response[11]);
+            let status_type = StatusType::from_byte(response[18]);
+
+            Ok(Response {
+                model,
+                status_type,
+                errors,
+                media: Media {
+                    media_type,
+                    width,
+                    length,
+                },
+            })
+        }
+
+        /// Fails with `ErrorKind::Printer` if this status reports an error.
+        pub fn into_result(self) -> crate::printer::Result<Response> {
+            if self.status_type == StatusType::ErrorOccurred {
+                bail!(crate::printer::ErrorKind::Printer(self.errors));
+            }
+            Ok(self)
+        }
+    }
+}
+
+/// Whether a discovered device can be printed to
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DeviceState {
+    /// The printer can be opened with `ThermalPrinter::new`
+    Ready,
+    /// The printer is in P-touch Editor Lite mode, presenting itself as a USB drive
+    EditorLiteMode,
 }
 
-fn printer_filter<T: rusb::UsbContext>(device: &rusb::Device<T>) -> bool {
-    let descriptor = device.device_descriptor().unwrap();
-    if descriptor.vendor_id() == constants::VENDOR_ID && descriptor.product_id() == 0x2049 {
-        eprintln!("You must disable Editor Lite mode on your QL-700 before you can print with it");
+impl DeviceState {
+    /// Instructions for the user to make the printer ready, if it isn't already
+    pub fn guidance(&self) -> Option<&'static str> {
+        match self {
+            DeviceState::Ready => None,
+            DeviceState::EditorLiteMode => Some(
+                "Press and hold the Editor Lite button until its green light turns off to switch the printer out of Editor Lite mode",
+            ),
+        }
     }
-    descriptor.vendor_id() == constants::VENDOR_ID
-        && constants::printer_name_from_id(descriptor.product_id()).is_some()
 }
 
-/// Get a vector of all attached and supported Brother QL printers as USB devices from which `ThermalPrinter` structs can be initialized.
-pub fn printers() -> Vec<rusb::Device<rusb::GlobalContext>> {
+/// A Brother QL printer found on the USB bus
+#[derive(Clone)]
+pub struct DiscoveredDevice<T: rusb::UsbContext> {
+    pub device: rusb::Device<T>,
+    pub model: &'static str,
+    pub state: DeviceState,
+}
+
+impl<T: rusb::UsbContext> std::fmt::Debug for DiscoveredDevice<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({:?}) on bus {} address {}",
+            self.model,
+            self.state,
+            self.device.bus_number(),
+            self.device.address()
+        )
+    }
+}
+
+pub(crate) fn discovered_device<T: rusb::UsbContext>(device: rusb::Device<T>) -> Option<DiscoveredDevice<T>> {
+    if let Some(discovered) = known_device(&device) {
+        return Some(discovered);
+    }
+    let descriptor = device.device_descriptor().ok()?;
+    if descriptor.vendor_id() != constants::VENDOR_ID {
+        return None;
+    }
+    let model = editor_lite_model(&device, &descriptor)?;
+    Some(DiscoveredDevice {
+        device,
+        model,
+        state: DeviceState::EditorLiteMode,
+    })
+}
+
+/// Recognizes printers by their product id alone, without communicating with the device.
+///
+/// Unlike `discovered_device`, this is safe to call from libusb's hotplug callbacks, but misses
+/// printers in Editor Lite mode that only their product name identifies.
+pub(crate) fn known_device<T: rusb::UsbContext>(device: &rusb::Device<T>) -> Option<DiscoveredDevice<T>> {
+    let descriptor = device.device_descriptor().ok()?;
+    if descriptor.vendor_id() != constants::VENDOR_ID {
+        return None;
+    }
+    let product_id = descriptor.product_id();
+    let (model, state) = if let Some(model) = constants::printer_name_from_id(product_id) {
+        (model, DeviceState::Ready)
+    } else if let Some(model) = constants::editor_lite_name_from_id(product_id) {
+        (model, DeviceState::EditorLiteMode)
+    } else {
+        return None;
+    };
+    Some(DiscoveredDevice {
+        device: device.clone(),
+        model,
+        state,
+    })
+}
+
+/// Recognizes printers in P-touch Editor Lite mode by their product name, if they present themselves as
+/// a USB mass storage device. Opens the device to read the name.
+fn editor_lite_model<T: rusb::UsbContext>(
+    device: &rusb::Device<T>,
+    descriptor: &rusb::DeviceDescriptor,
+) -> Option<&'static str> {
+    const MASS_STORAGE_CLASS: u8 = 0x08;
+    let config = device.active_config_descriptor().ok()?;
+    let mass_storage = config.interfaces().any(|interface| {
+        interface
+            .descriptors()
+            .any(|descriptor| descriptor.class_code() == MASS_STORAGE_CLASS)
+    });
+    if !mass_storage {
+        return None;
+    }
+    let product = device.open().ok()?.read_product_string_ascii(descriptor).ok()?;
+    constants::editor_lite_name_from_product(&product)
+}
+
+/// Get all attached Brother QL printers, including ones that can't be printed to in their current state.
+pub fn discover() -> Vec<DiscoveredDevice<rusb::GlobalContext>> {
     rusb::DeviceList::new()
         .unwrap()
         .iter()
-        .filter(printer_filter)
+        .filter_map(discovered_device)
         .collect()
 }
 
+/// Get a vector of all attached and supported Brother QL printers as USB devices from which `ThermalPrinter` structs can be initialized.
+pub fn printers() -> Vec<rusb::Device<rusb::GlobalContext>> {
+    discover()
+        .into_iter()
+        .filter_map(|discovered| match discovered.state {
+            DeviceState::Ready => Some(discovered.device),
+            _ => {
+                warn!("Skipping {:?}", discovered);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Size of the chunks `ThermalPrinter::write_raw` splits command streams into
+pub(crate) const RAW_CHUNK_SIZE: usize = 16 * 1024;
+
 /// The primary interface for dealing with Brother QL printers. Handles all USB communication with the printer.
 pub struct ThermalPrinter<T: rusb::UsbContext> {
     pub manufacturer: String,
@@ -96,6 +322,13 @@ pub struct ThermalPrinter<T: rusb::UsbContext> {
     handle: rusb::DeviceHandle<T>,
     in_endpoint: u8,
     out_endpoint: u8,
+    disconnected: AtomicBool,
+    config: PrinterConfig,
+    device_settings: Mutex<DeviceSettings>,
+    #[cfg(feature = "journal")]
+    journal: Option<std::sync::Arc<journal::Journal>>,
+    #[cfg(feature = "usage")]
+    usage: Option<std::sync::Arc<usage::UsageTracker>>,
 }
 impl<T: rusb::UsbContext> std::fmt::Debug for ThermalPrinter<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -107,16 +340,35 @@ impl<T: rusb::UsbContext> std::fmt::Debug for ThermalPrinter<T> {
     }
 }
 impl<T: rusb::UsbContext> ThermalPrinter<T> {
-    /// Create a new `ThermalPrinter` instance using a `rusb` USB device handle.
+    /// Create a new `ThermalPrinter` instance using a `rusb` USB device handle and reset the printer.
     ///
-    /// Obtain list of connected device handles by calling `printers()`.
+    /// Obtain list of connected device handles by calling `printers()`. Resetting aborts any job the
+    /// printer is currently working on, use `open()` to attach to a printer without side effects.
     pub fn new(device: rusb::Device<T>) -> Result<Self> {
-        let mut handle = device.open()?;
+        ThermalPrinter::with_config(device, PrinterConfig::default())
+    }
+
+    /// Same as `new()`, but with custom timeouts and retries for USB communication.
+    pub fn with_config(device: rusb::Device<T>, config: PrinterConfig) -> Result<Self> {
+        let printer = ThermalPrinter::open_with_config(device, config)?;
+        printer.reset()?;
+        Ok(printer)
+    }
+
+    /// Attach to a printer without sending it anything, e.g. to read its serial number or to wait for
+    /// a job another process is printing.
+    pub fn open(device: rusb::Device<T>) -> Result<Self> {
+        ThermalPrinter::open_with_config(device, PrinterConfig::default())
+    }
+
+    /// Same as `open()`, but with custom timeouts and retries for USB communication.
+    pub fn open_with_config(device: rusb::Device<T>, config: PrinterConfig) -> Result<Self> {
+        let handle = device.open()?;
         let mut in_endpoint: Option<u8> = None;
         let mut out_endpoint: Option<u8> = None;
 
-        let config = device.active_config_descriptor()?;
-        let interface = config
+        let config_descriptor = device.active_config_descriptor()?;
+        let interface = config_descriptor
             .interfaces()
             .next()
             .chain_err(|| "Brother QL printers should have exactly one interface")?;
@@ -153,16 +405,33 @@ impl<T: rusb::UsbContext> ThermalPrinter<T> {
             handle,
             in_endpoint: in_endpoint.unwrap(),
             out_endpoint: out_endpoint.unwrap(),
+            disconnected: AtomicBool::new(false),
+            config,
+            device_settings: Mutex::new(DeviceSettings::default()),
+            #[cfg(feature = "journal")]
+            journal: None,
+            #[cfg(feature = "usage")]
+            usage: None,
         };
+        Ok(printer)
+    }
 
-        // Reset printer
-        let clear_command = [0x00; 200];
-        ThermalPrinter::write(&printer, &clear_command)?;
-        let initialize_command = [0x1B, 0x40];
-        ThermalPrinter::write(&printer, &initialize_command)?;
+    /// Clears anything the printer has received so far and initializes it, aborting any job in progress.
+    pub fn reset(&self) -> Result<()> {
+        self.invalidate()?;
+        self.initialize()?;
+        self.get_status()?;
+        Ok(())
+    }
 
-        ThermalPrinter::get_status(&printer)?;
-        Ok(printer)
+    /// Sends a block of null bytes, which the printer ignores, to end any incomplete command it is waiting on.
+    pub fn invalidate(&self) -> Result<()> {
+        self.send_command(Invalidate)
+    }
+
+    /// Clears the print buffer and resets the printer's settings to their defaults.
+    pub fn initialize(&self) -> Result<()> {
+        self.send_command(Initialize)
     }
 
     /// Sends raster lines to the USB printer, begins printing, and immediately returns
@@ -173,94 +442,248 @@ impl<T: rusb::UsbContext> ThermalPrinter<T> {
     /// **Note:** the raster line width does not change for label media of different sizes. This means the
     /// printer can print out-of-bounds and even print on parts of the label not originally intended to
     /// contain content. Your rasterizer will have to figure out, given a media type, which parts of the
-    /// image will appear on the media and resize or shift margins and content accordingly.
+    /// image will appear on the media and resize or shift margins and content accordingly. Jobs with
+    /// content outside of the printable area of the loaded label are rejected unless `PrintJob::overprint`
+    /// is set.
     pub fn print(&self, job: &PrintJob) -> Result<status::Response> {
-        let raster_lines: &Vec<[u8; RASTER_LINE_LENGTH]> = &job.get_raster_lines();
-
-        self.apply_setting(SwitchToRasterMode)?;
-        self.print_info(raster_lines)?;
-
-        self.apply_setting(MirrorOrCut(job.mirrored, job.cut_on_end))?;
-        let resolution = match &job.resolution {
-            Resolution::Normal => NormalResMode(job.cut_on_end),
-            Resolution::High => HighResMode(job.cut_on_end),
-        };
-        self.apply_setting(resolution)?;
+        self.print_pages(std::slice::from_ref(job))
+    }
 
-        let label = self.current_label()?;
+    /// Sends several pages to the USB printer as one multi-page job, begins printing, and immediately returns
+    ///
+    /// The printer is only switched into raster mode once, so it doesn't re-initialize between labels. Every
+    /// page carries its own settings, so pages may differ in mirroring, cutting and resolution.
+    pub fn print_pages(&self, jobs: &[PrintJob]) -> Result<status::Response> {
+        let started = SystemTime::now();
+        let result = self.send_pages(jobs);
+        let sent = if result.is_ok() { jobs.len() } else { 0 };
+        self.record(jobs, sent, started, false, &result);
+        result
+    }
 
-        let margins_command = [0x1B, 0x69, 0x64, label.feed_margin, 0x00];
-        self.write(&margins_command)?;
+    /// Sends pages without recording them in the journal.
+    pub(crate) fn send_pages(&self, jobs: &[PrintJob]) -> Result<status::Response> {
+        let status = self.get_status()?;
+        let commands = encoder::encode_pages(&status, jobs)?;
+        #[cfg(feature = "metrics")]
+        metrics::job_started(
+            &self.serial_number,
+            jobs.iter().map(|job| job.raster_lines.len()).sum(),
+        );
+        for command in commands {
+            self.write(&command)?;
+        }
+        self.read()
+    }
 
-        for line in raster_lines.iter() {
-            let mut raster_command = vec![0x67, 0x00, RASTER_LINE_LENGTH as u8];
-            raster_command.extend_from_slice(line);
-            self.write(&raster_command)?;
+    /// Sends a command stream that was encoded elsewhere, such as with `encoder::encode_print_file`,
+    /// to the printer unchanged.
+    ///
+    /// The stream is written in chunks, so the write timeout applies to each chunk rather than to the
+    /// whole stream. Streams can be checked with `decoder::decode` and `decoder::check_job` first.
+    pub fn write_raw(&self, data: &[u8]) -> Result<()> {
+        for chunk in data.chunks(RAW_CHUNK_SIZE) {
+            self.write(chunk)?;
         }
+        Ok(())
+    }
 
-        self.send_command(StartPrint(true))?;
-        self.read()
+    /// Records every job printed from now on in `journal`.
+    ///
+    /// Jobs printed with `print_pages()` are recorded as soon as they were sent, as they aren't
+    /// awaited.
+    #[cfg(feature = "journal")]
+    pub fn set_journal(&mut self, journal: std::sync::Arc<journal::Journal>) {
+        self.journal = Some(journal);
     }
 
-    /// Print information command
-    /// Flags:
-    ///     PI_KIND      0x02    Paper type
-    ///     PI_WIDTH     0x04    Paper width
-    ///     PI_LENGTH    0x08    Paper length
-    ///     PI_QUALITY   0x40    Give priority to print quality
-    ///     PI_RECOVER   0x80    Always ON
-    fn print_info(&self, raster_lines: &Vec<[u8; RASTER_LINE_LENGTH]>) -> Result<()> {
-        let status = self.get_status()?;
-        const VALID_FLAGS: u8 = 0x80 | 0x02 | 0x04 | 0x08 | 0x40; // Everything enabled
-        let media_type: u8 = match status.media.media_type.to_byte() {
-            Some(value) => value,
-            None => return Err("No media loaded into printer".into()),
-        };
+    /// Adds the labels of every job printed from now on to the roll usage tracked by `tracker`,
+    /// including the pages printed before a job failed.
+    #[cfg(feature = "usage")]
+    pub fn set_usage_tracker(&mut self, tracker: std::sync::Arc<usage::UsageTracker>) {
+        self.usage = Some(tracker);
+    }
 
-        let starting_page = 0x01; // Starting page: 0; Other pages: 1.
-        let mut media_command = [
-            0x1B,
-            0x69,
-            0x7A,
-            VALID_FLAGS,
-            media_type,
-            status.media.width,
-            status.media.length,
-            0,
-            0,
-            0,
-            0,
-            starting_page,
-            0,
-        ];
-        let line_count = (raster_lines.len() as u32).to_le_bytes();
-        media_command[7..7 + 4].copy_from_slice(&line_count);
-        self.write(&media_command)
+    /// Records a job in the journal and the roll usage, if they are tracked, and its duration in the
+    /// metrics. `printed` pages of the job were printed, or sent if `awaited` is false, which tells
+    /// whether the job was awaited until it was printed.
+    #[allow(unused_variables)]
+    pub(crate) fn record(
+        &self,
+        jobs: &[PrintJob],
+        printed: usize,
+        started: SystemTime,
+        awaited: bool,
+        result: &Result<status::Response>,
+    ) {
+        #[cfg(feature = "metrics")]
+        if awaited && result.is_ok() {
+            let duration = started.elapsed().unwrap_or_default();
+            metrics::job_finished(&self.serial_number, duration);
+        }
+        #[cfg(feature = "usage")]
+        if let Some(tracker) = &self.usage {
+            let label = match result {
+                Ok(status) => status.media.label(),
+                // Pages printed before a job failed were printed on the roll that was tracked last
+                Err(_) => tracker
+                    .usage(&self.serial_number)
+                    .and_then(|usage| usage.label()),
+            };
+            if let (Some(label), true) = (label, printed > 0) {
+                if let Err(error) = tracker.record(&self.serial_number, &label, &jobs[..printed]) {
+                    warn!("Can't record roll usage: {}", error);
+                }
+            }
+        }
+        #[cfg(feature = "journal")]
+        if let Some(journal) = &self.journal {
+            let record = journal::JobRecord {
+                serial_number: &self.serial_number,
+                model: &self.model,
+                jobs,
+                started,
+                outcome: if awaited {
+                    journal::Outcome::Completed
+                } else {
+                    journal::Outcome::Sent
+                },
+                result,
+            };
+            if let Err(error) = journal.record(record) {
+                warn!("Can't record job in journal: {}", error);
+            }
+        }
     }
 
     /// Same as `print()` but will not return until the printer reports that it has finished printing.
     pub fn print_blocking(&self, job: &PrintJob) -> Result<()> {
-        self.print(job)?;
+        self.print_pages_blocking(std::slice::from_ref(job))
+    }
+
+    /// Same as `print_pages()` but will not return until the printer reports that every page has been printed.
+    pub fn print_pages_blocking(&self, jobs: &[PrintJob]) -> Result<()> {
+        let started = SystemTime::now();
+        let mut printed = 0;
+        let result = self
+            .send_pages(jobs)
+            .and_then(status::Response::into_result)
+            .and_then(|_| self.wait_for_pages(jobs.len(), &mut printed, |_| Ok(())));
+        self.record(jobs, printed, started, true, &result);
+        result.map(|_| ())
+    }
+
+    /// Waits until the printer reports that `pages` pages have been printed and returns the last status.
+    ///
+    /// Fails with `ErrorKind::Printer` if the printer reports an error in the meantime, and gives up if
+    /// it sends no status message for `PrinterConfig::progress_timeout`.
+    pub fn wait_for_completion(&self, pages: usize) -> Result<status::Response> {
+        self.wait_for_completion_forwarding(pages, |_| Ok(()))
+    }
+
+    /// Same as `wait_for_completion()`, but passes every status message the printer sends in the
+    /// meantime to `forward` as it was received, for example to relay it to a network client.
+    pub fn wait_for_completion_forwarding<F>(&self, pages: usize, forward: F) -> Result<status::Response>
+    where
+        F: FnMut(&[u8; status::RESPONSE_SIZE]) -> Result<()>,
+    {
+        self.wait_for_pages(pages, &mut 0, forward)
+    }
+
+    /// Same as `wait_for_completion_forwarding()`, but counts the pages reported as printed in
+    /// `printed`, which tells how far a job got when waiting for it fails.
+    pub(crate) fn wait_for_pages<F>(
+        &self,
+        pages: usize,
+        printed: &mut usize,
+        mut forward: F,
+    ) -> Result<status::Response>
+    where
+        F: FnMut(&[u8; status::RESPONSE_SIZE]) -> Result<()>,
+    {
+        let mut remaining = pages;
+        let mut progress = Instant::now();
         loop {
-            match self.read() {
-                Ok(ref response) if response.status_type == StatusType::PrintingCompleted => break,
-                _ => thread::sleep(Duration::from_millis(50)),
+            let message = match self.poll_bulk() {
+                Ok(message) => message,
+                Err(Error(ErrorKind::Disconnected, _)) => bail!(ErrorKind::Disconnected),
+                Err(error) if progress.elapsed() >= self.config.progress_timeout => {
+                    bail!(
+                        "The printer reported no progress for {} seconds, {} of {} pages were printed: {}",
+                        self.config.progress_timeout.as_secs(),
+                        pages - remaining,
+                        pages,
+                        error
+                    )
+                }
+                Err(_) => {
+                    thread::sleep(self.config.status_poll_interval);
+                    continue;
+                }
+            };
+            progress = Instant::now();
+            forward(&message)?;
+            match self.parse_status(&message) {
+                Ok(response) => match response.status_type {
+                    StatusType::PrintingCompleted => {
+                        *printed += 1;
+                        remaining = remaining.saturating_sub(1);
+                        if remaining == 0 {
+                            return Ok(response);
+                        }
+                    }
+                    StatusType::ErrorOccurred => bail!(ErrorKind::Printer(response.errors)),
+                    _ => thread::sleep(self.config.status_poll_interval),
+                },
+                _ => thread::sleep(self.config.status_poll_interval),
             }
         }
+    }
+
+    /// Applies a setting, checking first that the printer supports it if it is a device setting.
+    ///
+    /// Per-job settings such as cutting or the resolution are sent as part of every print job anyway, so
+    /// this is mostly useful for device settings.
+    pub fn apply_setting(&self, setting: PrinterSetting) -> Result<()> {
+        if setting.is_device_setting() {
+            let model = self.get_status()?.model;
+            if !model.supports_power_settings() {
+                bail!("The {} doesn't support {:?}", model.to_str(), setting);
+            }
+        }
+        let sequence = setting.get_byte_sequence();
+        debug!("Setting: {:x?}", sequence);
+        self.write(&sequence)?;
+        self.device_settings.lock().unwrap().record(&setting);
         Ok(())
     }
 
+    /// Whether the printer turns on automatically when it is connected to power.
+    pub fn set_auto_power_on(&self, on: bool) -> Result<()> {
+        self.apply_setting(PowerOnWhenConnected(on))
+    }
+
+    /// After how long without activity the printer turns itself off.
+    pub fn set_auto_power_off(&self, value: SleepTimerValue) -> Result<()> {
+        self.apply_setting(SleepTimer(value))
+    }
+
+    /// Device settings applied through this `ThermalPrinter`, remembered when they were applied.
+    ///
+    /// Nothing is read from the printer: the supported printers can't report their settings. Settings
+    /// that weren't applied since the printer was opened are `None`, even if they were changed before,
+    /// and settings changed elsewhere afterwards, such as with Brother's Printer Setting Tool, aren't
+    /// noticed.
+    pub fn device_settings(&self) -> DeviceSettings {
+        self.device_settings.lock().unwrap().clone()
+    }
+
     /// Get the currently loaded label size.
     pub fn current_label(&self) -> Result<constants::Label> {
-        let media = self.get_status()?.media;
-        constants::label_data(
-            media.width,
-            match media.length {
-                0 => None,
-                _ => Some(media.length),
-            },
-        )
-        .ok_or("Unknown media loaded in printer".into())
+        self.get_status()?
+            .media
+            .label()
+            .chain_err(|| "Unknown media loaded in printer")
     }
 
     /// Get the current status of the printer including possible errors, media type, and model name.
@@ -269,79 +692,124 @@ impl<T: rusb::UsbContext> ThermalPrinter<T> {
         self.read()
     }
 
+    /// Reads the next status message from the printer without parsing it, for forwarding it unchanged.
+    ///
+    /// Besides answering status requests, printers send status messages on their own when they start
+    /// and finish printing a page and when an error occurs.
+    pub fn read_status_message(&self) -> Result<[u8; status::RESPONSE_SIZE]> {
+        self.read_bulk()
+    }
+
     fn read(&self) -> Result<status::Response> {
-        let response = match self.read_bulk() {
-            Ok(bytes) => bytes,
-            Err(error) => return Err(error),
-        };
+        self.parse_status(&self.read_bulk()?)
+    }
 
-        let model = PrinterModel::from_byte(response[4]);
+    /// Reads a status message once, failing with a timeout if the printer didn't send one.
+    #[cfg(feature = "async")]
+    pub(crate) fn poll_status(&self) -> Result<status::Response> {
+        self.parse_status(&self.poll_bulk()?)
+    }
 
-        let mut errors = Vec::new();
+    fn parse_status(&self, message: &[u8; status::RESPONSE_SIZE]) -> Result<status::Response> {
+        let response = status::Response::from_bytes(message)?;
+        #[cfg(feature = "metrics")]
+        metrics::status_received(&self.serial_number, &response);
+        Ok(response)
+    }
 
-        fn error_if(byte: u8, flag: u8, message: &'static str, errors: &mut Vec<&'static str>) {
-            if byte & flag != 0 {
-                errors.push(message);
-            }
-        }
-        error_if(response[8], 0x01, "No media when printing", &mut errors);
-        error_if(response[8], 0x02, "End of media", &mut errors);
-        error_if(response[8], 0x04, "Tape cutter jam", &mut errors);
-        error_if(response[8], 0x10, "Main unit in use", &mut errors);
-        error_if(response[8], 0x80, "Fan doesn't work", &mut errors);
-        error_if(response[9], 0x04, "Transmission error", &mut errors);
-        error_if(response[9], 0x10, "Cover open", &mut errors);
-        error_if(response[9], 0x40, "Cannot feed", &mut errors);
-        error_if(response[9], 0x80, "System error", &mut errors);
-
-        let width = response[10];
-        let length = response[17];
-
-        let media_type = MediaType::from_byte(response[11]);
-        let status_type = StatusType::from_byte(response[18]);
-
-        Ok(status::Response {
-            model,
-            status_type,
-            errors,
-            media: status::Media {
-                media_type,
-                width,
-                length,
-            },
-        })
+    fn read_bulk(&self) -> Result<[u8; status::RESPONSE_SIZE]> {
+        self.read_message(PrinterConfig::is_transient)
+    }
+
+    /// Reads a status message without retrying, for polling, where a read timing out only means that
+    /// there is no message yet.
+    fn poll_bulk(&self) -> Result<[u8; status::RESPONSE_SIZE]> {
+        self.read_message(|_| false)
     }
 
-    fn read_bulk(&self) -> Result<[u8; 32]> {
-        const RECEIVE_SIZE: usize = 32;
-        let mut response = [0; RECEIVE_SIZE];
-        let bytes_read =
+    fn read_message(&self, retry: fn(&rusb::Error) -> bool) -> Result<[u8; status::RESPONSE_SIZE]> {
+        let mut response = [0; status::RESPONSE_SIZE];
+        let bytes_read = self.transfer(self.in_endpoint, retry, || {
             self.handle
-                .read_bulk(self.in_endpoint, &mut response, Duration::from_millis(500))?;
+                .read_bulk(self.in_endpoint, &mut response, self.config.read_timeout)
+        })?;
+
+        trace!("Read: {:02x?}", &response[..bytes_read]);
 
-        if bytes_read != RECEIVE_SIZE || response[0] != 0x80 {
+        if bytes_read != status::RESPONSE_SIZE {
             return Err("Invalid response received from printer".into());
         }
         Ok(response)
     }
 
-    fn apply_setting(&self, setting: PrinterSetting) -> Result<()> {
-        let sequence = setting.get_byte_sequence();
-        println!("Setting: {:x?}", sequence);
-        self.write(&sequence)
-    }
-
     fn send_command(&self, command: Command) -> Result<()> {
         let sequence = command.get_byte_sequence();
-        println!("Command: {:x?}", sequence);
+        debug!("Command: {:x?}", sequence);
         self.write(sequence)
     }
 
     fn write(&self, data: &[u8]) -> Result<()> {
-        self.handle
-            .write_bulk(self.out_endpoint, data, Duration::from_millis(500))?;
+        trace!("Write: {:02x?}", data);
+        self.transfer(self.out_endpoint, PrinterConfig::is_retryable_write, || {
+            self.handle
+                .write_bulk(self.out_endpoint, data, self.config.write_timeout)
+        })?;
+        #[cfg(feature = "metrics")]
+        metrics::bytes_sent(&self.serial_number, data.len());
         Ok(())
     }
+
+    /// Runs a USB transfer, retrying it as configured on the errors `retry` accepts.
+    fn transfer<R, F>(&self, endpoint: u8, retry: fn(&rusb::Error) -> bool, mut transfer: F) -> Result<R>
+    where
+        F: FnMut() -> rusb::Result<R>,
+    {
+        self.check_connected()?;
+        let mut attempt = 0;
+        loop {
+            match transfer() {
+                Ok(result) => return Ok(result),
+                Err(error) if attempt < self.config.retries && retry(&error) => {
+                    attempt += 1;
+                    warn!(
+                        "USB transfer failed: {}, retrying ({}/{})",
+                        error, attempt, self.config.retries
+                    );
+                    if error == rusb::Error::Pipe {
+                        self.handle
+                            .clear_halt(endpoint)
+                            .map_err(|error| self.usb_error(error))?;
+                    }
+                    thread::sleep(self.config.backoff(attempt));
+                }
+                Err(error) => return Err(self.usb_error(error)),
+            }
+        }
+    }
+
+    /// Whether the printer is still attached, as far as known from the last USB transfer.
+    ///
+    /// Once a printer was unplugged, all further operations fail with `ErrorKind::Disconnected`.
+    pub fn is_connected(&self) -> bool {
+        !self.disconnected.load(Ordering::Relaxed)
+    }
+
+    fn check_connected(&self) -> Result<()> {
+        if !self.is_connected() {
+            bail!(ErrorKind::Disconnected);
+        }
+        Ok(())
+    }
+
+    fn usb_error(&self, error: rusb::Error) -> Error {
+        if error == rusb::Error::NoDevice {
+            self.disconnected.store(true, Ordering::Relaxed);
+            #[cfg(feature = "metrics")]
+            metrics::disconnected(&self.serial_number);
+            return ErrorKind::Disconnected.into();
+        }
+        error.into()
+    }
 }
 
 pub trait Printable {
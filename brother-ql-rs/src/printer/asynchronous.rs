@@ -0,0 +1,348 @@
+//! Asynchronous printer API for the tokio runtime
+//!
+//! USB printers are driven on tokio's blocking thread pool, network printers are talked to over a
+//! TCP connection to their raw printing port (usually 9100). Both speak the same command stream,
+//! produced by the `encoder`. Network printers are given up on after timeouts, so the runtime needs
+//! its timer enabled.
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::stream::{self, Stream};
+use log::{debug, trace};
+use rusb::GlobalContext;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::sync::Mutex;
+use tokio::time;
+
+use crate::printer::command::Command;
+use crate::printer::job::PrintJob;
+use crate::printer::status_type::StatusType;
+use crate::printer::config::PrinterConfig;
+use crate::printer::{encoder, status, Error, ErrorKind, Result, ThermalPrinter, RAW_CHUNK_SIZE};
+
+enum Backend {
+    Usb(Arc<ThermalPrinter<GlobalContext>>),
+    Tcp(Mutex<Connection>),
+}
+
+/// A Brother QL printer whose operations are futures
+pub struct AsyncPrinter {
+    backend: Backend,
+}
+
+impl AsyncPrinter {
+    /// Opens a USB printer without sending it anything, see `ThermalPrinter::open`.
+    ///
+    /// Call `reset()` first to abort whatever the printer is working on.
+    pub async fn open(device: rusb::Device<GlobalContext>) -> Result<AsyncPrinter> {
+        let printer = blocking(move || ThermalPrinter::open(device)).await?;
+        Ok(AsyncPrinter::from_usb(printer))
+    }
+
+    pub fn from_usb(printer: ThermalPrinter<GlobalContext>) -> AsyncPrinter {
+        AsyncPrinter {
+            backend: Backend::Usb(Arc::new(printer)),
+        }
+    }
+
+    /// Connects to a network printer and asks it for its status.
+    ///
+    /// The printer has to answer status requests over the connection, which not all network
+    /// capable models do. Call `reset()` first to abort whatever the printer is working on.
+    pub async fn connect<A: ToSocketAddrs>(address: A) -> Result<AsyncPrinter> {
+        AsyncPrinter::connect_with_config(address, PrinterConfig::default()).await
+    }
+
+    /// Same as `connect()`, but with custom timeouts. Every read of a reply and every chunk written
+    /// has to finish within the read and write timeout, retries aren't supported.
+    pub async fn connect_with_config<A: ToSocketAddrs>(
+        address: A,
+        config: PrinterConfig,
+    ) -> Result<AsyncPrinter> {
+        let connecting = TcpStream::connect(address);
+        let stream = with_timeout(config.write_timeout, async { Ok(connecting.await?) }).await?;
+        stream.set_nodelay(true)?;
+        let printer = AsyncPrinter {
+            backend: Backend::Tcp(Mutex::new(Connection {
+                stream,
+                received: Vec::new(),
+                config,
+            })),
+        };
+        printer.get_status().await?;
+        Ok(printer)
+    }
+
+    /// Clears anything the printer has received so far and initializes it, aborting any job in
+    /// progress, see `ThermalPrinter::reset`.
+    pub async fn reset(&self) -> Result<()> {
+        match &self.backend {
+            Backend::Usb(printer) => {
+                let printer = printer.clone();
+                blocking(move || printer.reset()).await
+            }
+            Backend::Tcp(connection) => {
+                let mut connection = connection.lock().await;
+                for command in [Command::Invalidate, Command::Initialize, Command::GetStatus] {
+                    connection.write(command.get_byte_sequence()).await?;
+                }
+                connection.read().await?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Get the current status of the printer, see `ThermalPrinter::get_status`.
+    pub async fn get_status(&self) -> Result<status::Response> {
+        match &self.backend {
+            Backend::Usb(printer) => {
+                let printer = printer.clone();
+                blocking(move || printer.get_status()).await
+            }
+            Backend::Tcp(connection) => {
+                let mut connection = connection.lock().await;
+                connection.write(Command::GetStatus.get_byte_sequence()).await?;
+                connection.read().await
+            }
+        }
+    }
+
+    /// Sends a job to the printer and returns once it has started printing, see `ThermalPrinter::print`.
+    pub async fn print(&self, job: PrintJob) -> Result<status::Response> {
+        self.print_pages(vec![job]).await
+    }
+
+    /// Sends several pages as one job, see `ThermalPrinter::print_pages`.
+    pub async fn print_pages(&self, jobs: Vec<PrintJob>) -> Result<status::Response> {
+        match &self.backend {
+            Backend::Usb(printer) => {
+                let printer = printer.clone();
+                blocking(move || printer.print_pages(&jobs)).await
+            }
+            Backend::Tcp(connection) => {
+                let mut connection = connection.lock().await;
+                connection.write(Command::GetStatus.get_byte_sequence()).await?;
+                let status = connection.read().await?;
+                let commands = encoder::encode_pages(&status, &jobs)?;
+                connection.write(&commands.concat()).await?;
+                connection.read().await
+            }
+        }
+    }
+
+    /// Sends several pages as one job and returns once all of them are printed.
+    pub async fn print_pages_to_completion(&self, jobs: Vec<PrintJob>) -> Result<()> {
+        let pages = jobs.len();
+        self.print_pages(jobs).await?.into_result()?;
+        self.wait_for_completion(pages).await
+    }
+
+    /// Waits until the printer reports that `pages` pages have been printed.
+    pub async fn wait_for_completion(&self, pages: usize) -> Result<()> {
+        let mut remaining = pages;
+        while remaining > 0 {
+            let status = self.next_status().await?;
+            match status.status_type {
+                StatusType::PrintingCompleted => remaining -= 1,
+                StatusType::ErrorOccurred => bail!(ErrorKind::Printer(status.errors)),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// All status messages the printer sends on its own, such as phase changes and completed pages.
+    ///
+    /// The stream ends after the printer was disconnected.
+    pub fn status_updates(&self) -> impl Stream<Item = Result<status::Response>> + '_ {
+        stream::unfold(Some(self), |printer| async move {
+            let printer = printer?;
+            let status = printer.next_status().await;
+            let next = match status {
+                Err(Error(ErrorKind::Disconnected, _)) => None,
+                _ => Some(printer),
+            };
+            Some((status, next))
+        })
+    }
+
+    /// Waits for the next status message without requesting one.
+    async fn next_status(&self) -> Result<status::Response> {
+        match &self.backend {
+            // Every read ends after the read timeout, so a dropped future doesn't leave a thread behind
+            // that keeps reading status messages
+            Backend::Usb(printer) => loop {
+                let printer = printer.clone();
+                match blocking(move || printer.poll_status()).await {
+                    Err(Error(ErrorKind::USB(rusb::Error::Timeout), _)) => continue,
+                    result => return result,
+                }
+            },
+            // A status message received in part when the future is dropped is completed by the next read
+            Backend::Tcp(connection) => loop {
+                match connection.lock().await.read().await {
+                    Err(Error(ErrorKind::Io(error), _)) if error.kind() == std::io::ErrorKind::TimedOut => {
+                        continue
+                    }
+                    result => return result,
+                }
+            },
+        }
+    }
+}
+
+async fn blocking<F, T>(operation: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    match tokio::task::spawn_blocking(operation).await {
+        Ok(result) => result,
+        Err(error) => bail!("Printer task failed: {}", error),
+    }
+}
+
+/// A connection to a network printer
+struct Connection {
+    stream: TcpStream,
+    /// The start of a status message whose remaining bytes weren't received yet
+    received: Vec<u8>,
+    config: PrinterConfig,
+}
+
+impl Connection {
+    async fn write(&mut self, data: &[u8]) -> Result<()> {
+        debug!("Write: {} bytes", data.len());
+        trace!("Write: {:02x?}", data);
+        for chunk in data.chunks(RAW_CHUNK_SIZE) {
+            let writing = self.stream.write_all(chunk);
+            with_timeout(self.config.write_timeout, async { writing.await.map_err(tcp_error) }).await?;
+        }
+        let flushing = self.stream.flush();
+        with_timeout(self.config.write_timeout, async { flushing.await.map_err(tcp_error) }).await
+    }
+
+    /// Reads the next status message, failing if it doesn't arrive within the read timeout.
+    ///
+    /// Cancel safe: the bytes of a message received before the future was dropped or timed out are
+    /// kept for the next read, so messages stay aligned.
+    async fn read(&mut self) -> Result<status::Response> {
+        let read_timeout = self.config.read_timeout;
+        with_timeout(read_timeout, self.read_message()).await
+    }
+
+    async fn read_message(&mut self) -> Result<status::Response> {
+        while self.received.len() < status::RESPONSE_SIZE {
+            let mut buffer = [0; status::RESPONSE_SIZE];
+            let missing = status::RESPONSE_SIZE - self.received.len();
+            let read = self
+                .stream
+                .read(&mut buffer[..missing])
+                .await
+                .map_err(tcp_error)?;
+            if read == 0 {
+                bail!(ErrorKind::Disconnected);
+            }
+            self.received.extend_from_slice(&buffer[..read]);
+        }
+        let mut response = [0; status::RESPONSE_SIZE];
+        response.copy_from_slice(&self.received);
+        self.received.clear();
+        trace!("Read: {:02x?}", response);
+        status::Response::from_bytes(&response)
+    }
+}
+
+/// Fails with an I/O error of kind `TimedOut` if `operation` doesn't finish within `timeout`.
+async fn with_timeout<F, T>(timeout: Duration, operation: F) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    match time::timeout(timeout, operation).await {
+        Ok(result) => result,
+        Err(_) => Err(std::io::Error::from(std::io::ErrorKind::TimedOut).into()),
+    }
+}
+
+fn tcp_error(error: std::io::Error) -> Error {
+    match error.kind() {
+        std::io::ErrorKind::UnexpectedEof
+        | std::io::ErrorKind::ConnectionReset
+        | std::io::ErrorKind::ConnectionAborted
+        | std::io::ErrorKind::BrokenPipe => ErrorKind::Disconnected.into(),
+        _ => error.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+    use tokio::net::TcpListener;
+
+    fn status_message(status_type: u8) -> [u8; status::RESPONSE_SIZE] {
+        let mut message = [0; status::RESPONSE_SIZE];
+        message[0] = 0x80;
+        message[10] = 62;
+        message[18] = status_type;
+        message
+    }
+
+    /// Connects to a fake printer, returning its end of the connection once it answered the status
+    /// request sent when connecting.
+    async fn connect(config: PrinterConfig) -> (AsyncPrinter, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let fake = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 3];
+            stream.read_exact(&mut request).await.unwrap();
+            assert_eq!(request, Command::GetStatus.get_byte_sequence());
+            stream.write_all(&status_message(0)).await.unwrap();
+            stream
+        });
+        let printer = AsyncPrinter::connect_with_config(address, config).await.unwrap();
+        (printer, fake.await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn keeps_status_messages_aligned_when_reads_are_dropped() {
+        let (printer, mut fake) = connect(PrinterConfig::default()).await;
+        let message = status_message(1);
+        fake.write_all(&message[..10]).await.unwrap();
+        let dropped = time::timeout(Duration::from_millis(50), printer.next_status()).await;
+        assert!(dropped.is_err());
+
+        fake.write_all(&message[10..]).await.unwrap();
+        fake.write_all(&status_message(2)).await.unwrap();
+        let status = printer.next_status().await.unwrap();
+        assert_eq!(status.status_type, StatusType::PrintingCompleted);
+        let status = printer.next_status().await.unwrap();
+        assert_eq!(status.status_type, StatusType::ErrorOccurred);
+    }
+
+    #[tokio::test]
+    async fn times_out_when_the_printer_does_not_answer() {
+        let config = PrinterConfig {
+            read_timeout: Duration::from_millis(50),
+            ..PrinterConfig::default()
+        };
+        let (printer, _fake) = connect(config).await;
+        let error = printer.get_status().await.unwrap_err();
+        match error.kind() {
+            ErrorKind::Io(error) => assert_eq!(error.kind(), std::io::ErrorKind::TimedOut),
+            other => panic!("Unexpected error: {}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_closed_connections_as_disconnected() {
+        let (printer, fake) = connect(PrinterConfig::default()).await;
+        drop(fake);
+        let updates: Vec<_> = printer.status_updates().collect().await;
+        assert_eq!(updates.len(), 1);
+        assert!(matches!(updates[0], Err(Error(ErrorKind::Disconnected, _))));
+    }
+}
@@ -0,0 +1,155 @@
+//! Batch printing of labels from CSV data
+//!
+//! Every row of a CSV file becomes one label. All labels are sent to the printer as a single
+//! multi-page job so it doesn't re-initialize between them. Turning a row into raster lines is up
+//! to the caller, who passes a render function mapping each `Record` to a `PrintJob`.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::printer::constants::Label;
+use crate::printer::job::PrintJob;
+use crate::printer::{Result, ThermalPrinter};
+
+/// A single CSV row, keyed by the column names from the header row
+pub type Record = HashMap<String, String>;
+
+/// A field that is added to every record, counting up from `start` for each row
+pub struct SerialField {
+    pub name: String,
+    pub start: u64,
+    pub step: u64,
+    /// Minimum number of digits, padded with leading zeroes
+    pub width: usize,
+}
+
+impl SerialField {
+    pub fn new(name: &str, start: u64) -> SerialField {
+        SerialField {
+            name: name.to_string(),
+            start,
+            step: 1,
+            width: 0,
+        }
+    }
+
+    fn value(&self, row: usize) -> String {
+        let value = self.start + self.step * row as u64;
+        format!("{:0width$}", value, width = self.width)
+    }
+}
+
+pub struct BatchOptions {
+    /// How many identical labels to print for every row
+    pub copies: usize,
+    pub serial_fields: Vec<SerialField>,
+    /// Label to show the printable area of in dry-run previews
+    pub label: Option<Label>,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        BatchOptions {
+            copies: 1,
+            serial_fields: Vec::new(),
+            label: None,
+        }
+    }
+}
+
+/// Rows of label data read from a CSV file
+pub struct Batch {
+    records: Vec<Record>,
+}
+
+impl Batch {
+    /// Reads records from CSV data. The first row is used as the header.
+    pub fn from_csv<R: Read>(reader: R) -> Result<Batch> {
+        let mut reader = csv::Reader::from_reader(reader);
+        let headers = reader.headers()?.clone();
+        let mut records = Vec::new();
+        for row in reader.records() {
+            let row = row?;
+            records.push(
+                headers
+                    .iter()
+                    .zip(row.iter())
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect(),
+            );
+        }
+        Ok(Batch { records })
+    }
+
+    pub fn from_csv_path<P: AsRef<Path>>(path: P) -> Result<Batch> {
+        Batch::from_csv(File::open(path)?)
+    }
+
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    /// Returns one record per label that will be printed, with serial fields filled in and rows
+    /// repeated according to `options.copies`.
+    pub fn expand(&self, options: &BatchOptions) -> Vec<Record> {
+        let mut labels = Vec::with_capacity(self.records.len() * options.copies);
+        for (row, record) in self.records.iter().enumerate() {
+            let mut record = record.clone();
+            for field in options.serial_fields.iter() {
+                record.insert(field.name.clone(), field.value(row));
+            }
+            for _ in 0..options.copies {
+                labels.push(record.clone());
+            }
+        }
+        labels
+    }
+
+    /// Renders every label into its own page.
+    pub fn render<F>(&self, options: &BatchOptions, render: F) -> Result<Vec<PrintJob>>
+    where
+        F: FnMut(&Record) -> Result<PrintJob>,
+    {
+        self.expand(options).iter().map(render).collect()
+    }
+
+    /// Renders and prints every label as a single multi-page job, returning once all pages are printed.
+    pub fn print<T, F>(
+        &self,
+        printer: &ThermalPrinter<T>,
+        options: &BatchOptions,
+        render: F,
+    ) -> Result<()>
+    where
+        T: rusb::UsbContext,
+        F: FnMut(&Record) -> Result<PrintJob>,
+    {
+        let pages = self.render(options, render)?;
+        printer.print_pages_blocking(&pages)
+    }
+
+    /// Renders a preview of every label to a PNG file in `directory` instead of printing it.
+    ///
+    /// Returns the paths of the written files, named `label-0001.png`, `label-0002.png`, etc.
+    pub fn dry_run<P, F>(
+        &self,
+        directory: P,
+        options: &BatchOptions,
+        render: F,
+    ) -> Result<Vec<PathBuf>>
+    where
+        P: AsRef<Path>,
+        F: FnMut(&Record) -> Result<PrintJob>,
+    {
+        let mut paths = Vec::new();
+        for (index, page) in self.render(options, render)?.iter().enumerate() {
+            let path = directory
+                .as_ref()
+                .join(format!("label-{:04}.png", index + 1));
+            page.to_image(options.label.as_ref()).write_png(&path)?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+}
@@ -0,0 +1,380 @@
+//! Catalogue of label media sold by Brother
+//!
+//! Entries are identified by their size (e.g. `29x90`, `62` for continuous tape, `d24` for round
+//! labels) and list the DK part numbers of all rolls with that size and colour.
+use crate::printer::constants::{Label, WidthLength};
+use crate::printer::model::PrinterModel;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Shape {
+    Continuous,
+    Rectangle,
+    Round,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Colors {
+    Black,
+    /// Black and red on white, requires a two-color capable printer
+    BlackRed,
+}
+
+/// Contents of a full roll
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Roll {
+    /// Length of continuous tape
+    Millimeters(u32),
+    /// Number of die-cut labels
+    Labels(u32),
+}
+
+#[derive(Debug)]
+pub struct CatalogueEntry {
+    pub name: &'static str,
+    pub part_numbers: &'static [&'static str],
+    pub description: &'static str,
+    pub shape: Shape,
+    pub colors: Colors,
+    /// Printers that can handle this media, all printers if empty
+    pub models: &'static [PrinterModel],
+    /// What a roll of the first part number holds, if known
+    pub roll: Option<Roll>,
+    pub label: Label,
+}
+
+impl CatalogueEntry {
+    pub fn supports_model(&self, model: &PrinterModel) -> bool {
+        (self.colors == Colors::Black || model.supports_two_color())
+            && (self.models.is_empty() || self.models.contains(model))
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+            || self
+                .part_numbers
+                .iter()
+                .any(|part_number| part_number.eq_ignore_ascii_case(name))
+    }
+}
+
+/// All known label media
+pub fn catalogue() -> &'static [CatalogueEntry] {
+    &CATALOGUE
+}
+
+/// Finds a label by its name (e.g. `62x100`) or DK part number (e.g. `DK-11202`).
+pub fn find(name: &str) -> Option<&'static CatalogueEntry> {
+    CATALOGUE.iter().find(|entry| entry.matches(name))
+}
+
+/// Finds a single-colour label by its tape size in mm, using a length of `0` for continuous tape.
+pub fn find_by_size(width: u32, length: u32) -> Option<&'static CatalogueEntry> {
+    CATALOGUE.iter().find(|entry| {
+        entry.colors == Colors::Black
+            && entry.label.tape_size.0 == width
+            && entry.label.tape_size.1 == length
+    })
+}
+
+const WIDE_PRINTERS: &[PrinterModel] = &[PrinterModel::QL1050, PrinterModel::QL1060N];
+
+const fn continuous(width: u32, dots: u32, dots_printable: u32, right_margin: u8) -> Label {
+    Label {
+        tape_size: WidthLength(width, 0),
+        dots: WidthLength(dots, 0),
+        dots_printable: WidthLength(dots_printable, 0),
+        right_margin,
+        feed_margin: 35,
+    }
+}
+
+const fn die_cut(
+    tape_size: (u32, u32),
+    dots: (u32, u32),
+    dots_printable: (u32, u32),
+    right_margin: u8,
+) -> Label {
+    Label {
+        tape_size: WidthLength(tape_size.0, tape_size.1),
+        dots: WidthLength(dots.0, dots.1),
+        dots_printable: WidthLength(dots_printable.0, dots_printable.1),
+        right_margin,
+        feed_margin: 0,
+    }
+}
+
+static CATALOGUE: [CatalogueEntry; 27] = [
+    CatalogueEntry {
+        name: "12",
+        part_numbers: &["DK-22214"],
+        description: "12mm continuous paper tape",
+        shape: Shape::Continuous,
+        colors: Colors::Black,
+        models: &[],
+        roll: Some(Roll::Millimeters(30_480)),
+        label: continuous(12, 142, 106, 29),
+    },
+    CatalogueEntry {
+        name: "29",
+        part_numbers: &["DK-22210", "DK-22211"],
+        description: "29mm continuous tape",
+        shape: Shape::Continuous,
+        colors: Colors::Black,
+        models: &[],
+        roll: Some(Roll::Millimeters(30_480)),
+        label: continuous(29, 342, 306, 6),
+    },
+    CatalogueEntry {
+        name: "38",
+        part_numbers: &["DK-22225"],
+        description: "38mm continuous paper tape",
+        shape: Shape::Continuous,
+        colors: Colors::Black,
+        models: &[],
+        roll: Some(Roll::Millimeters(30_480)),
+        label: continuous(38, 449, 413, 12),
+    },
+    CatalogueEntry {
+        name: "50",
+        part_numbers: &["DK-22223"],
+        description: "50mm continuous paper tape",
+        shape: Shape::Continuous,
+        colors: Colors::Black,
+        models: &[],
+        roll: Some(Roll::Millimeters(30_480)),
+        label: continuous(50, 590, 554, 12),
+    },
+    CatalogueEntry {
+        name: "54",
+        part_numbers: &["DK-N55224"],
+        description: "54mm continuous non-adhesive paper tape",
+        shape: Shape::Continuous,
+        colors: Colors::Black,
+        models: &[],
+        roll: Some(Roll::Millimeters(30_480)),
+        label: continuous(54, 636, 590, 0),
+    },
+    CatalogueEntry {
+        name: "62",
+        part_numbers: &[
+            "DK-22205", "DK-22212", "DK-22606", "DK-22113", "DK-44205", "DK-44605",
+        ],
+        description: "62mm continuous tape",
+        shape: Shape::Continuous,
+        colors: Colors::Black,
+        models: &[],
+        roll: Some(Roll::Millimeters(30_480)),
+        label: continuous(62, 732, 696, 12),
+    },
+    CatalogueEntry {
+        name: "62red",
+        part_numbers: &["DK-22251"],
+        description: "62mm continuous black and red paper tape",
+        shape: Shape::Continuous,
+        colors: Colors::BlackRed,
+        models: &[],
+        roll: Some(Roll::Millimeters(15_240)),
+        label: continuous(62, 732, 696, 12),
+    },
+    CatalogueEntry {
+        name: "102",
+        part_numbers: &["DK-22243"],
+        description: "102mm continuous paper tape",
+        shape: Shape::Continuous,
+        colors: Colors::Black,
+        models: WIDE_PRINTERS,
+        roll: Some(Roll::Millimeters(30_480)),
+        label: continuous(102, 1200, 1164, 12),
+    },
+    CatalogueEntry {
+        name: "103",
+        part_numbers: &["DK-22246"],
+        description: "103.6mm continuous paper tape",
+        shape: Shape::Continuous,
+        colors: Colors::Black,
+        models: WIDE_PRINTERS,
+        roll: Some(Roll::Millimeters(30_480)),
+        label: continuous(104, 1224, 1200, 12),
+    },
+    CatalogueEntry {
+        name: "17x54",
+        part_numbers: &["DK-11204"],
+        description: "Multi purpose labels",
+        shape: Shape::Rectangle,
+        colors: Colors::Black,
+        models: &[],
+        roll: Some(Roll::Labels(400)),
+        label: die_cut((17, 54), (201, 636), (165, 566), 0),
+    },
+    CatalogueEntry {
+        name: "17x87",
+        part_numbers: &["DK-11203"],
+        description: "File folder labels",
+        shape: Shape::Rectangle,
+        colors: Colors::Black,
+        models: &[],
+        roll: Some(Roll::Labels(300)),
+        label: die_cut((17, 87), (201, 1026), (165, 956), 0),
+    },
+    CatalogueEntry {
+        name: "23x23",
+        part_numbers: &["DK-11221"],
+        description: "Square paper labels",
+        shape: Shape::Rectangle,
+        colors: Colors::Black,
+        models: &[],
+        roll: Some(Roll::Labels(1000)),
+        label: die_cut((23, 23), (272, 272), (202, 202), 42),
+    },
+    CatalogueEntry {
+        name: "29x42",
+        part_numbers: &[],
+        description: "29x42mm labels",
+        shape: Shape::Rectangle,
+        colors: Colors::Black,
+        models: &[],
+        roll: None,
+        label: die_cut((29, 42), (342, 495), (306, 425), 6),
+    },
+    CatalogueEntry {
+        name: "29x90",
+        part_numbers: &["DK-11201"],
+        description: "Standard address labels",
+        shape: Shape::Rectangle,
+        colors: Colors::Black,
+        models: &[],
+        roll: Some(Roll::Labels(400)),
+        label: die_cut((29, 90), (342, 1061), (306, 991), 6),
+    },
+    CatalogueEntry {
+        name: "38x90",
+        part_numbers: &["DK-11208"],
+        description: "Large address labels",
+        shape: Shape::Rectangle,
+        colors: Colors::Black,
+        models: &[],
+        roll: Some(Roll::Labels(400)),
+        label: die_cut((38, 90), (449, 1061), (413, 991), 12),
+    },
+    CatalogueEntry {
+        name: "39x48",
+        part_numbers: &[],
+        description: "39x48mm labels",
+        shape: Shape::Rectangle,
+        colors: Colors::Black,
+        models: &[],
+        roll: None,
+        label: die_cut((39, 48), (461, 565), (425, 495), 6),
+    },
+    CatalogueEntry {
+        name: "52x29",
+        part_numbers: &[],
+        description: "52x29mm labels",
+        shape: Shape::Rectangle,
+        colors: Colors::Black,
+        models: &[],
+        roll: None,
+        label: die_cut((52, 29), (614, 341), (578, 271), 0),
+    },
+    CatalogueEntry {
+        name: "54x29",
+        part_numbers: &[],
+        description: "54x29mm labels",
+        shape: Shape::Rectangle,
+        colors: Colors::Black,
+        models: &[],
+        roll: None,
+        label: die_cut((54, 29), (630, 341), (598, 271), 60),
+    },
+    CatalogueEntry {
+        name: "60x86",
+        part_numbers: &["DK-11234"],
+        description: "Name badge labels",
+        shape: Shape::Rectangle,
+        colors: Colors::Black,
+        models: &[],
+        roll: Some(Roll::Labels(260)),
+        label: die_cut((60, 87), (708, 1024), (672, 954), 18),
+    },
+    CatalogueEntry {
+        name: "62x29",
+        part_numbers: &[],
+        description: "62x29mm labels",
+        shape: Shape::Rectangle,
+        colors: Colors::Black,
+        models: &[],
+        roll: None,
+        label: die_cut((62, 29), (732, 341), (696, 271), 12),
+    },
+    CatalogueEntry {
+        name: "62x100",
+        part_numbers: &["DK-11202"],
+        description: "Shipping labels",
+        shape: Shape::Rectangle,
+        colors: Colors::Black,
+        models: &[],
+        roll: Some(Roll::Labels(300)),
+        label: die_cut((62, 100), (732, 1179), (696, 1109), 12),
+    },
+    CatalogueEntry {
+        name: "102x51",
+        part_numbers: &["DK-11240"],
+        description: "Large multi purpose labels",
+        shape: Shape::Rectangle,
+        colors: Colors::Black,
+        models: WIDE_PRINTERS,
+        roll: Some(Roll::Labels(600)),
+        label: die_cut((102, 51), (1200, 596), (1164, 526), 12),
+    },
+    CatalogueEntry {
+        name: "102x152",
+        part_numbers: &["DK-11241"],
+        description: "Large shipping labels",
+        shape: Shape::Rectangle,
+        colors: Colors::Black,
+        models: WIDE_PRINTERS,
+        roll: Some(Roll::Labels(200)),
+        label: die_cut((102, 153), (1200, 1804), (1164, 1660), 12),
+    },
+    CatalogueEntry {
+        name: "103x164",
+        part_numbers: &["DK-11247"],
+        description: "Large shipping labels",
+        shape: Shape::Rectangle,
+        colors: Colors::Black,
+        models: WIDE_PRINTERS,
+        roll: Some(Roll::Labels(180)),
+        label: die_cut((104, 164), (1224, 1941), (1200, 1822), 12),
+    },
+    CatalogueEntry {
+        name: "d12",
+        part_numbers: &["DK-11219"],
+        description: "12mm round labels",
+        shape: Shape::Round,
+        colors: Colors::Black,
+        models: &[],
+        roll: Some(Roll::Labels(1200)),
+        label: die_cut((12, 12), (142, 142), (94, 94), 113),
+    },
+    CatalogueEntry {
+        name: "d24",
+        part_numbers: &["DK-11218"],
+        description: "24mm round labels",
+        shape: Shape::Round,
+        colors: Colors::Black,
+        models: &[],
+        roll: Some(Roll::Labels(1000)),
+        label: die_cut((24, 24), (284, 284), (236, 236), 42),
+    },
+    CatalogueEntry {
+        name: "d58",
+        part_numbers: &["DK-11207"],
+        description: "58mm round CD/DVD film labels",
+        shape: Shape::Round,
+        colors: Colors::Black,
+        models: &[],
+        roll: Some(Roll::Labels(100)),
+        label: die_cut((58, 58), (688, 688), (618, 618), 51),
+    },
+];
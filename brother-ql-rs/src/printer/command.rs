@@ -2,10 +2,14 @@
 pub static GET_STATUS: [u8; 3] = [0x1B, 0x69, 0x53];
 pub static START_PRINT_LAST_PAGE: [u8; 1] = [0x1A];
 pub static START_PRINT: [u8; 1] = [0x0C];
+pub static INVALIDATE: [u8; 200] = [0x00; 200];
+pub static INITIALIZE: [u8; 2] = [0x1B, 0x40];
 
 pub enum Command {
     GetStatus,
     StartPrint(bool),
+    Invalidate,
+    Initialize,
 }
 
 impl Command {
@@ -17,7 +21,9 @@ impl Command {
                 &START_PRINT_LAST_PAGE
             } else {
                 &START_PRINT
-            }
+            },
+            Command::Invalidate => &INVALIDATE,
+            Command::Initialize => &INITIALIZE,
         }
     }
 }
\ No newline at end of file
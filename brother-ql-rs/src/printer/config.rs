@@ -0,0 +1,76 @@
+//! Timeouts and retry behaviour of USB communication
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct PrinterConfig {
+    /// How long a single USB write may take
+    pub write_timeout: Duration,
+    /// How long to wait for a status message from the printer
+    pub read_timeout: Duration,
+    /// How often to check for status messages while waiting for a job to finish
+    pub status_poll_interval: Duration,
+    /// How long to wait for the next status message while waiting for a job to finish, before giving
+    /// up on the job
+    pub progress_timeout: Duration,
+    /// How often a transfer failing with a transient error is retried: reads that stalled or timed
+    /// out, and writes that stalled. Writes that timed out may have been sent in part, so they aren't.
+    pub retries: u32,
+    /// Delay before the first retry, doubled for every further attempt up to `MAX_RETRY_BACKOFF`
+    pub retry_backoff: Duration,
+}
+
+impl Default for PrinterConfig {
+    fn default() -> Self {
+        PrinterConfig {
+            write_timeout: Duration::from_millis(500),
+            read_timeout: Duration::from_millis(500),
+            status_poll_interval: Duration::from_millis(50),
+            progress_timeout: Duration::from_secs(60),
+            retries: 0,
+            retry_backoff: Duration::from_millis(100),
+        }
+    }
+}
+
+/// Longest delay between two retries
+pub const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(5);
+
+impl PrinterConfig {
+    pub(crate) fn is_transient(error: &rusb::Error) -> bool {
+        matches!(error, rusb::Error::Timeout | rusb::Error::Pipe)
+    }
+
+    /// Whether a failed write can be repeated without sending any part of it twice
+    pub(crate) fn is_retryable_write(error: &rusb::Error) -> bool {
+        *error == rusb::Error::Pipe
+    }
+
+    /// Delay before the given retry, starting at 1
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        self.retry_backoff
+            .checked_mul(2u32.saturating_pow(attempt - 1))
+            .map_or(MAX_RETRY_BACKOFF, |backoff| backoff.min(MAX_RETRY_BACKOFF))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let config = PrinterConfig::default();
+        assert_eq!(config.backoff(1), Duration::from_millis(100));
+        assert_eq!(config.backoff(2), Duration::from_millis(200));
+        assert_eq!(config.backoff(4), Duration::from_millis(800));
+        assert_eq!(config.backoff(10), MAX_RETRY_BACKOFF);
+        assert_eq!(config.backoff(100), MAX_RETRY_BACKOFF);
+    }
+
+    #[test]
+    fn writes_are_only_retried_after_stalls() {
+        assert!(PrinterConfig::is_retryable_write(&rusb::Error::Pipe));
+        assert!(!PrinterConfig::is_retryable_write(&rusb::Error::Timeout));
+        assert!(PrinterConfig::is_transient(&rusb::Error::Timeout));
+    }
+}
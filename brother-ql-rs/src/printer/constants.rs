@@ -1,9 +1,13 @@
 //! Label media and USB ID constants used by Brother QL printers
 
-#[derive(Debug, Copy, Clone)]
+use std::ops::Range;
+
+use crate::printer::{catalogue, registry};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct WidthLength(pub u32, pub u32);
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Label {
 	pub tape_size: WidthLength,
 	pub dots: WidthLength,
@@ -12,141 +16,44 @@ pub struct Label {
 	pub feed_margin: u8,
 }
 
+impl Label {
+	/// Dots of a raster line, as sent to the printer, that end up in the printable area of the label
+	pub fn printable_dots(&self) -> Range<usize> {
+		let start = self.right_margin as usize;
+		start..start + self.dots_printable.0 as usize
+	}
+
+	pub fn is_die_cut(&self) -> bool {
+		self.tape_size.1 != 0
+	}
+}
+
 /// Returns a corresponding label type given dimensions returned by the printer
 ///
-/// These are predefined label rolls types sold by Brother and defined in the spec
+/// These are predefined label rolls types sold by Brother, listed in the `catalogue`, unless a label
+/// of the same size was registered with `registry::register_label`
 pub fn label_data(width: u8, length: Option<u8>) -> Option<Label> {
-	if let Some(length) = length {
-		// Die cut label
-		match (width, length) {
-			(17, 54) => Some(Label {
-				tape_size: WidthLength(17, 54),
-				dots: WidthLength(201, 636),
-				dots_printable: WidthLength(165, 566),
-				right_margin: 0,
-				feed_margin: 0,
-			}),
-			(17, 87) => Some(Label {
-				tape_size: WidthLength(17, 87),
-				dots: WidthLength(201, 1026),
-				dots_printable: WidthLength(165, 956),
-				right_margin: 0,
-				feed_margin: 0,
-			}),
-			(23, 23) => Some(Label {
-				tape_size: WidthLength(23, 23),
-				dots: WidthLength(272, 272),
-				dots_printable: WidthLength(202, 202),
-				right_margin: 42,
-				feed_margin: 0,
-			}),
-			(29, 42) => Some(Label {
-				tape_size: WidthLength(29, 42),
-				dots: WidthLength(342, 495),
-				dots_printable: WidthLength(306, 425),
-				right_margin: 6,
-				feed_margin: 0,
-			}),
-			(29, 90) => Some(Label {
-				tape_size: WidthLength(29, 90),
-				dots: WidthLength(342, 1061),
-				dots_printable: WidthLength(306, 991),
-				right_margin: 6,
-				feed_margin: 0,
-			}),
-			(39, 90) => Some(Label {
-				tape_size: WidthLength(38, 90),
-				dots: WidthLength(449, 1061),
-				dots_printable: WidthLength(413, 991),
-				right_margin: 12,
-				feed_margin: 0,
-			}),
-			(39, 48) => Some(Label {
-				tape_size: WidthLength(39, 48),
-				dots: WidthLength(461, 565),
-				dots_printable: WidthLength(425, 495),
-				right_margin: 6,
-				feed_margin: 0,
-			}),
-			(52, 29) => Some(Label {
-				tape_size: WidthLength(52, 29),
-				dots: WidthLength(614, 341),
-				dots_printable: WidthLength(578, 271),
-				right_margin: 0,
-				feed_margin: 0,
-			}),
-			(62, 29) => Some(Label {
-				tape_size: WidthLength(62, 29),
-				dots: WidthLength(732, 341),
-				dots_printable: WidthLength(696, 271),
-				right_margin: 12,
-				feed_margin: 0,
-			}),
-			(62, 100) => Some(Label {
-				tape_size: WidthLength(62, 100),
-				dots: WidthLength(732, 1179),
-				dots_printable: WidthLength(696, 1109),
-				right_margin: 12,
-				feed_margin: 0,
-			}),
-			_ => None
-		}
+	if let Some(label) = registry::lookup(width, length) {
+		return Some(label);
 	}
-	else {
-		// Continuous label
-		match width {
-			12 => Some(Label {
-				tape_size: WidthLength(12, 0),
-				dots: WidthLength(142, 0),
-				dots_printable: WidthLength(106, 0),
-				right_margin: 29,
-				feed_margin: 35
-			}),
-			29 => Some(Label {
-				tape_size: WidthLength(29, 0),
-				dots: WidthLength(342, 0),
-				dots_printable: WidthLength(306, 0),
-				right_margin: 6,
-				feed_margin: 35
-			}),
-			38 => Some(Label {
-				tape_size: WidthLength(38, 0),
-				dots: WidthLength(449, 0),
-				dots_printable: WidthLength(413, 0),
-				right_margin: 12,
-				feed_margin: 35
-			}),
-			50 => Some(Label {
-				tape_size: WidthLength(50, 0),
-				dots: WidthLength(590, 0),
-				dots_printable: WidthLength(554, 0),
-				right_margin: 12,
-				feed_margin: 35
-			}),
-			54 => Some(Label {
-				tape_size: WidthLength(54, 0),
-				dots: WidthLength(636, 0),
-				dots_printable: WidthLength(590, 0),
-				right_margin: 0,
-				feed_margin: 35
-			}),
-			62 => Some(Label {
-				tape_size: WidthLength(62, 0),
-				dots: WidthLength(732, 0),
-				dots_printable: WidthLength(696, 0),
-				right_margin: 12,
-				feed_margin: 35
-			}),
-			102 => Some(Label {
-				tape_size: WidthLength(102, 0),
-				dots: WidthLength(1200, 0),
-				dots_printable: WidthLength(1164, 0),
-				right_margin: 12,
-				feed_margin: 35
-			}),
-			_ => None
+	catalogue::find_by_size(width as u32, length.unwrap_or(0) as u32).map(|entry| entry.label)
+}
+
+/// Every label `label_data` knows of, with one label per tape size
+///
+/// Labels registered with `registry::register_label` come first, followed by the single-colour labels
+/// of the `catalogue` of other sizes.
+pub fn known_labels() -> Vec<Label> {
+	let mut labels: Vec<Label> = registry::custom_labels();
+	for entry in catalogue::catalogue() {
+		if entry.colors != catalogue::Colors::Black {
+			continue;
+		}
+		if !labels.iter().any(|label| label.tape_size == entry.label.tape_size) {
+			labels.push(entry.label);
 		}
 	}
+	labels
 }
 
 pub static BLACK_PIXEL: u8 = 0;
@@ -174,3 +81,44 @@ pub fn printer_name_from_id(id: u16) -> Option<&'static str> {
 		_ => None
 	}
 }
+
+/// Get the model name of a printer in P-touch Editor Lite mode from its USB Product ID
+///
+/// In this mode the printer presents itself as a USB mass storage device and can't be printed to.
+pub fn editor_lite_name_from_id(id: u16) -> Option<&'static str> {
+	match id {
+		0x2049 => Some("QL-700"),
+		_ => None
+	}
+}
+
+/// Models with a P-touch Editor Lite mode
+pub const EDITOR_LITE_MODELS: [&str; 6] = ["QL-700", "QL-800", "QL-810W", "QL-820NWB", "QL-1100", "QL-1110NWB"];
+
+/// Get the model name of a printer in P-touch Editor Lite mode from the product name of the USB drive
+/// it presents itself as, for models whose Product ID in this mode isn't listed in `editor_lite_name_from_id`
+pub fn editor_lite_name_from_product(product: &str) -> Option<&'static str> {
+	let product = product.to_ascii_uppercase();
+	EDITOR_LITE_MODELS
+		.iter()
+		.find(|model| product.split(|c: char| c.is_whitespace() || c == '_').any(|word| word == **model))
+		.copied()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn editor_lite_models() {
+		assert_eq!(editor_lite_name_from_id(0x2049), Some("QL-700"));
+		assert_eq!(editor_lite_name_from_id(0x2042), None);
+		assert_eq!(editor_lite_name_from_product("QL-800"), Some("QL-800"));
+		assert_eq!(editor_lite_name_from_product("Brother QL-810W"), Some("QL-810W"));
+		assert_eq!(editor_lite_name_from_product("QL-820NWB Storage"), Some("QL-820NWB"));
+		assert_eq!(editor_lite_name_from_product("ql-1100"), Some("QL-1100"));
+		assert_eq!(editor_lite_name_from_product("QL-1110NWB"), Some("QL-1110NWB"));
+		assert_eq!(editor_lite_name_from_product("QL-8000"), None);
+		assert_eq!(editor_lite_name_from_product("USB Flash Drive"), None);
+	}
+}
@@ -0,0 +1,285 @@
+//! Parsing of the command stream understood by Brother QL printers
+//!
+//! The reverse of the `encoder`, used to check command streams that were produced elsewhere, such as
+//! by a CUPS filter or a client sending raw jobs over the network, before they reach a printer.
+use crate::printer::setting::PrinterSetting::{
+    CutEvery, HighResMode, MirrorOrCut, NormalResMode, PowerOnWhenConnected, SleepTimer,
+};
+use crate::printer::setting::{PrinterSetting, SleepTimerValue};
+use crate::printer::Result;
+
+/// A single command of a command stream
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    /// A run of null bytes, ending any incomplete command
+    Invalidate(usize),
+    Initialize,
+    GetStatus,
+    /// Switches the command mode, `1` being raster mode
+    SwitchMode(u8),
+    /// Automatic status notifications, `0` to enable them
+    StatusNotification(u8),
+    /// Media type, width, length, raster line count and page of the following page
+    PrintInfo([u8; 10]),
+    Setting(PrinterSetting),
+    FeedMargin(u16),
+    /// Compression of the following raster lines, `0x02` being TIFF (PackBits)
+    Compression(u8),
+    /// A raster line, compressed if compression was enabled
+    RasterLine(Vec<u8>),
+    /// A raster line without any printed dots
+    ZeroLine,
+    Print,
+    PrintLast,
+}
+
+/// Splits a command stream into instructions, failing on unknown or incomplete commands.
+pub fn decode(data: &[u8]) -> Result<Vec<Instruction>> {
+    let mut instructions = Vec::new();
+    let mut position = 0;
+    while position < data.len() {
+        match decode_next(&data[position..]) {
+            Ok(Some((instruction, length))) => {
+                instructions.push(instruction);
+                position += length;
+            }
+            Ok(None) => bail!("Command stream ends in the middle of a command"),
+            Err(error) => bail!("{} at byte {}", error, position),
+        }
+    }
+    Ok(instructions)
+}
+
+/// Decodes the first command of `data`, returning it along with its length in bytes.
+///
+/// Returns `None` if `data` ends before the command does, so streams arriving in pieces, such as
+/// from a network connection, can be decoded as they arrive.
+pub fn decode_next(data: &[u8]) -> Result<Option<(Instruction, usize)>> {
+    let decoded = match data {
+        [] => return Ok(None),
+        [0x00, ..] => {
+            let nulls = data.iter().take_while(|&&byte| byte == 0x00).count();
+            (Instruction::Invalidate(nulls), nulls)
+        }
+        [0x1B, 0x40, ..] => (Instruction::Initialize, 2),
+        [0x1B, 0x69, 0x53, ..] => (Instruction::GetStatus, 3),
+        [0x1B, 0x69, 0x61, mode, ..] => (Instruction::SwitchMode(*mode), 4),
+        [0x1B, 0x69, 0x21, mode, ..] => (Instruction::StatusNotification(*mode), 4),
+        [0x1B, 0x69, 0x7A, ..] => {
+            if data.len() < 13 {
+                return Ok(None);
+            }
+            let mut info = [0; 10];
+            info.copy_from_slice(&data[3..13]);
+            (Instruction::PrintInfo(info), 13)
+        }
+        [0x1B, 0x69, 0x4D, mode, ..] => (
+            Instruction::Setting(MirrorOrCut(mode & 0x80 != 0, mode & 0x40 != 0)),
+            4,
+        ),
+        [0x1B, 0x69, 0x41, pages, ..] => (Instruction::Setting(CutEvery(*pages)), 4),
+        [0x1B, 0x69, 0x4B, mode, ..] => {
+            let cut_at_end = mode & 0x08 != 0;
+            let setting = if mode & 0x40 != 0 {
+                HighResMode(cut_at_end)
+            } else {
+                NormalResMode(cut_at_end)
+            };
+            (Instruction::Setting(setting), 4)
+        }
+        [0x1B, 0x69, 0x64, low, high, ..] => {
+            (Instruction::FeedMargin(u16::from_le_bytes([*low, *high])), 5)
+        }
+        [0x1B, 0x69, 0x55, setting, 0x00, value, ..] => {
+            (Instruction::Setting(device_setting(*setting, *value)?), 6)
+        }
+        [0x4D, mode, ..] => (Instruction::Compression(*mode), 2),
+        [0x67, 0x00, length, ..] => {
+            let end = 3 + *length as usize;
+            if data.len() < end {
+                return Ok(None);
+            }
+            (Instruction::RasterLine(data[3..end].to_vec()), end)
+        }
+        [0x5A, ..] => (Instruction::ZeroLine, 1),
+        [0x0C, ..] => (Instruction::Print, 1),
+        [0x1A, ..] => (Instruction::PrintLast, 1),
+        // Commands cut off before their end
+        [0x1B] | [0x1B, 0x69] | [0x1B, 0x69, _] | [0x1B, 0x69, 0x64, _] | [0x4D] | [0x67] | [0x67, 0x00] => {
+            return Ok(None)
+        }
+        [0x1B, 0x69, 0x55, ..] if data.len() < 6 => return Ok(None),
+        _ => bail!("Unknown command {:02x?}", &data[..data.len().min(3)]),
+    };
+    Ok(Some(decoded))
+}
+
+fn device_setting(setting: u8, value: u8) -> Result<PrinterSetting> {
+    let setting = match (setting, value) {
+        (0x70, 0x00) => PowerOnWhenConnected(true),
+        (0x70, 0x01) => PowerOnWhenConnected(false),
+        (0x41, 0x00) => SleepTimer(SleepTimerValue::Disable),
+        (0x41, 0x01) => SleepTimer(SleepTimerValue::TurnOffAfter10Minutes),
+        (0x41, 0x02) => SleepTimer(SleepTimerValue::TurnOffAfter20Minutes),
+        (0x41, 0x03) => SleepTimer(SleepTimerValue::TurnOffAfter30Minutes),
+        (0x41, 0x04) => SleepTimer(SleepTimerValue::TurnOffAfter40Minutes),
+        (0x41, 0x05) => SleepTimer(SleepTimerValue::TurnOffAfter50Minutes),
+        (0x41, 0x06) => SleepTimer(SleepTimerValue::TurnOffAfter60Minutes),
+        _ => bail!("Unknown device setting {:02x} with value {:02x}", setting, value),
+    };
+    Ok(setting)
+}
+
+/// Checks that a decoded command stream is a complete print job, returning how many pages it prints.
+///
+/// Every page has to be announced with print information and end with a print command, the last
+/// one with `PrintLast`.
+pub fn check_job(instructions: &[Instruction]) -> Result<usize> {
+    let mut pages = 0;
+    let mut page_started = false;
+    let mut finished = false;
+    for instruction in instructions {
+        match instruction {
+            Instruction::PrintInfo(_) if finished => bail!("Pages follow the last page of the job"),
+            Instruction::PrintInfo(_) => page_started = true,
+            Instruction::RasterLine(_) | Instruction::ZeroLine if !page_started => {
+                bail!("Raster lines are sent before the print information of their page")
+            }
+            Instruction::Print | Instruction::PrintLast if !page_started => {
+                bail!("Page {} has no print information", pages + 1)
+            }
+            Instruction::Print | Instruction::PrintLast => {
+                pages += 1;
+                page_started = false;
+                finished = *instruction == Instruction::PrintLast;
+            }
+            _ => {}
+        }
+    }
+    if pages == 0 {
+        bail!("The job doesn't print any pages");
+    }
+    if !finished {
+        bail!("The job doesn't end with the last page");
+    }
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::encoder::encode_print_file;
+    use crate::printer::job::{Cut, Margin, PrintJob};
+    use crate::printer::model::PrinterModel;
+    use crate::printer::setting::Resolution;
+    use crate::printer::test_support::label;
+
+    fn print_file(name: &str, pages: usize) -> Vec<u8> {
+        let label = label(name);
+        let mut pixels = vec![0xFF; 20 * 30];
+        pixels[..20].fill(0);
+        let job = PrintJob::from_grayscale(&pixels, 20, &label).unwrap();
+        encode_print_file(PrinterModel::QL700, &label, &vec![job; pages]).unwrap()
+    }
+
+    /// Decodes a stream arriving in chunks of `size` bytes, like a network connection would
+    fn decode_in_chunks(data: &[u8], size: usize) -> Vec<Instruction> {
+        let mut instructions = Vec::new();
+        let mut buffer = Vec::new();
+        for chunk in data.chunks(size) {
+            buffer.extend_from_slice(chunk);
+            while let Some((instruction, length)) = decode_next(&buffer).unwrap() {
+                // Runs of nulls may continue in the next chunk
+                if matches!(instruction, Instruction::Invalidate(_)) && length == buffer.len() {
+                    break;
+                }
+                instructions.push(instruction);
+                buffer.drain(..length);
+            }
+        }
+        if let Some((instruction, _)) = decode_next(&buffer).unwrap() {
+            instructions.push(instruction);
+        }
+        instructions
+    }
+
+    #[test]
+    fn decodes_encoded_jobs() {
+        let instructions = decode(&print_file("62", 3)).unwrap();
+        assert_eq!(instructions[0], Instruction::Invalidate(200));
+        assert_eq!(instructions[1], Instruction::Initialize);
+        assert!(instructions.contains(&Instruction::SwitchMode(1)));
+        assert!(instructions.contains(&Instruction::Setting(NormalResMode(true))));
+        assert_eq!(
+            instructions.iter().filter(|i| matches!(i, Instruction::PrintInfo(_))).count(),
+            3
+        );
+        assert_eq!(check_job(&instructions).unwrap(), 3);
+        assert_eq!(check_job(&decode(&print_file("29x90", 1)).unwrap()).unwrap(), 1);
+    }
+
+    #[test]
+    fn decodes_job_settings() {
+        let label = label("62");
+        let mut job = PrintJob::from_grayscale(&[0; 4], 4, &label)
+            .unwrap()
+            .with_resolution(Resolution::High);
+        job.cut = Cut::Every(2);
+        job.feed_margin = Some(Margin::Dots(50));
+        let instructions = decode(&encode_print_file(PrinterModel::QL700, &label, &[job]).unwrap()).unwrap();
+        assert!(instructions.contains(&Instruction::Setting(HighResMode(true))));
+        assert!(instructions.contains(&Instruction::Setting(CutEvery(2))));
+        assert!(instructions.contains(&Instruction::Setting(MirrorOrCut(true, true))));
+        assert!(instructions.contains(&Instruction::FeedMargin(50)));
+        assert_eq!(check_job(&instructions).unwrap(), 1);
+    }
+
+    #[test]
+    fn decodes_streams_split_across_reads() {
+        let data = print_file("62", 2);
+        let whole = decode(&data).unwrap();
+        for size in [1, 2, 3, 5, 7, 13, 64, 101] {
+            assert_eq!(decode_in_chunks(&data, size), whole, "chunks of {} bytes", size);
+        }
+    }
+
+    #[test]
+    fn incomplete_commands_need_more_data() {
+        let data = print_file("62", 1);
+        let mut position = 0;
+        while position < data.len() {
+            let (instruction, length) = decode_next(&data[position..]).unwrap().unwrap();
+            if !matches!(instruction, Instruction::Invalidate(_)) {
+                for end in position..position + length {
+                    assert_eq!(
+                        decode_next(&data[position..end]).unwrap(),
+                        None,
+                        "{:?} cut off after {} bytes",
+                        instruction,
+                        end - position
+                    );
+                }
+            }
+            position += length;
+        }
+        assert!(decode(&[0x67, 0x00, 0x05, 0xFF]).is_err());
+        // Without the last print command the stream decodes, but isn't a complete job
+        assert!(check_job(&decode(&data[..data.len() - 1]).unwrap()).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_streams() {
+        assert!(decode(&[0x1B, 0x69, 0x99, 0x00]).is_err());
+        assert!(decode(&[0x1B, 0x69, 0x55, 0x41, 0x00, 0x09]).is_err());
+        assert!(check_job(&[]).is_err());
+        assert!(check_job(&[Instruction::RasterLine(vec![0; 90]), Instruction::PrintLast]).is_err());
+        assert!(check_job(&[Instruction::PrintInfo([0; 10]), Instruction::Print]).is_err());
+        assert!(check_job(&[
+            Instruction::PrintInfo([0; 10]),
+            Instruction::PrintLast,
+            Instruction::PrintInfo([0; 10]),
+            Instruction::PrintLast,
+        ])
+        .is_err());
+    }
+}
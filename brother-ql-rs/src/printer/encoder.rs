@@ -0,0 +1,229 @@
+//! Translation of print jobs into the command stream understood by Brother QL printers
+//!
+//! The encoder doesn't depend on how the printer is connected, so the same commands can be sent
+//! over USB, a network socket or written to a file.
+use log::debug;
+
+use crate::printer::command::Command::{Initialize, Invalidate, StartPrint};
+use crate::printer::constants::{Label, RASTER_LINE_LENGTH};
+use crate::printer::job::{Cut, PrintJob};
+use crate::printer::model::PrinterModel;
+use crate::printer::setting::PrinterSetting::{
+    CutEvery, HighResMode, MirrorOrCut, NormalResMode, SwitchToRasterMode,
+};
+use crate::printer::setting::{PrinterSetting, Resolution};
+use crate::printer::status;
+use crate::printer::{Result, ResultExt};
+
+/// Smallest feed margin in dots continuous tape is printed with: 35 dots, 3 mm at 300 dpi, the
+/// margin the printers feed by default. The same minimum is used for all models, as their smallest
+/// margins aren't documented separately.
+pub const MIN_FEED_MARGIN: u16 = 35;
+
+/// Encodes pages as one multi-page job for a printer that reported `status`.
+///
+/// Returns the individual commands in the order they have to be sent. Jobs are validated against
+/// the model and loaded media first, see `PrintJob::validate`.
+pub fn encode_pages(status: &status::Response, jobs: &[PrintJob]) -> Result<Vec<Vec<u8>>> {
+    let label = status.media.label().chain_err(|| "Unknown media loaded in printer")?;
+    encode(&status.model, &status.media, &label, jobs)
+}
+
+/// Encodes pages as a complete print file for the given model and label, without asking a printer.
+///
+/// The file starts by clearing and initializing the printer, so it can be sent to a printer as is,
+/// for example by a CUPS filter or with `ThermalPrinter::write_raw`.
+pub fn encode_print_file(model: PrinterModel, label: &Label, jobs: &[PrintJob]) -> Result<Vec<u8>> {
+    let media = status::Media::from_label(label);
+    let mut file = Invalidate.get_byte_sequence().to_vec();
+    file.extend_from_slice(Initialize.get_byte_sequence());
+    for command in encode(&model, &media, label, jobs)? {
+        file.extend_from_slice(&command);
+    }
+    Ok(file)
+}
+
+fn encode(
+    model: &PrinterModel,
+    media: &status::Media,
+    label: &Label,
+    jobs: &[PrintJob],
+) -> Result<Vec<Vec<u8>>> {
+    if jobs.is_empty() {
+        bail!("A print job must contain at least one page");
+    }
+    if !model.supports_high_resolution()
+        && jobs.iter().any(|job| job.resolution == Resolution::High)
+    {
+        bail!(
+            "The {} doesn't support high resolution printing",
+            model.to_str()
+        );
+    }
+    for (index, job) in jobs.iter().enumerate() {
+        job.validate(label)
+            .chain_err(|| format!("Page {} can't be printed", index + 1))?;
+    }
+
+    let mut commands = vec![setting(SwitchToRasterMode)];
+    for (index, job) in jobs.iter().enumerate() {
+        let raster_lines = job.get_raster_lines();
+        commands.push(print_info(media, &raster_lines, index == 0)?);
+
+        // Auto cut cuts after every given number of pages, cutting at the end is a separate flag
+        let auto_cut = matches!(job.cut, Cut::Every(_));
+        commands.push(setting(MirrorOrCut(job.mirrored, auto_cut)));
+        if let Cut::Every(pages) = job.cut {
+            commands.push(setting(CutEvery(pages)));
+        }
+        let cut_at_end = job.cut != Cut::Never && !job.chain_printing;
+        let resolution = match &job.resolution {
+            Resolution::Normal => NormalResMode(cut_at_end),
+            Resolution::High => HighResMode(cut_at_end),
+        };
+        commands.push(setting(resolution));
+
+        let feed_margin = job.feed_margin_dots(label)?;
+        if feed_margin < MIN_FEED_MARGIN && !label.is_die_cut() {
+            bail!(
+                "Page {}: feed margin of {} dots is below the minimum of {} dots",
+                index + 1,
+                feed_margin,
+                MIN_FEED_MARGIN
+            );
+        }
+        let feed_margin = feed_margin.to_le_bytes();
+        commands.push(vec![0x1B, 0x69, 0x64, feed_margin[0], feed_margin[1]]);
+
+        for line in raster_lines.iter() {
+            let mut raster_command = vec![0x67, 0x00, RASTER_LINE_LENGTH as u8];
+            raster_command.extend_from_slice(line);
+            commands.push(raster_command);
+        }
+
+        commands.push(StartPrint(index == jobs.len() - 1).get_byte_sequence().to_vec());
+    }
+    Ok(commands)
+}
+
+fn setting(setting: PrinterSetting) -> Vec<u8> {
+    let sequence = setting.get_byte_sequence();
+    debug!("Setting: {:x?}", sequence);
+    sequence
+}
+
+/// Print information command
+/// Flags:
+///     PI_KIND      0x02    Paper type
+///     PI_WIDTH     0x04    Paper width
+///     PI_LENGTH    0x08    Paper length
+///     PI_QUALITY   0x40    Give priority to print quality
+///     PI_RECOVER   0x80    Always ON
+fn print_info(
+    media: &status::Media,
+    raster_lines: &[[u8; RASTER_LINE_LENGTH]],
+    first_page: bool,
+) -> Result<Vec<u8>> {
+    const VALID_FLAGS: u8 = 0x80 | 0x02 | 0x04 | 0x08 | 0x40; // Everything enabled
+    let media_type: u8 = match media.media_type.to_byte() {
+        Some(value) => value,
+        None => return Err("No media loaded into printer".into()),
+    };
+
+    let starting_page = if first_page { 0x00 } else { 0x01 }; // Starting page: 0; Other pages: 1.
+    let mut media_command = vec![
+        0x1B,
+        0x69,
+        0x7A,
+        VALID_FLAGS,
+        media_type,
+        media.width,
+        media.length,
+        0,
+        0,
+        0,
+        0,
+        starting_page,
+        0,
+    ];
+    // In high resolution mode every line is half as tall, but it still counts as one raster line
+    let line_count = (raster_lines.len() as u32).to_le_bytes();
+    media_command[7..7 + 4].copy_from_slice(&line_count);
+    Ok(media_command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::job::Margin;
+    use crate::printer::test_support::label;
+
+    fn encode_job(model: PrinterModel, job: PrintJob) -> Result<Vec<Vec<u8>>> {
+        let label = label("62");
+        let media = status::Media::from_label(&label);
+        encode(&model, &media, &label, &[job])
+    }
+
+    fn job(cut: Cut) -> PrintJob {
+        let label = label("62");
+        let mut job = PrintJob::from_grayscale(&[0; 10], 10, &label).unwrap();
+        job.cut = cut;
+        job
+    }
+
+    /// The auto cut bit of the mode and the expanded mode of the encoded job
+    fn modes(commands: &[Vec<u8>]) -> (u8, u8) {
+        let byte = |command: u8| {
+            commands
+                .iter()
+                .find(|sequence| sequence.starts_with(&[0x1B, 0x69, command]))
+                .unwrap()[3]
+        };
+        (byte(0x4D) & 0x40, byte(0x4B))
+    }
+
+    fn cut_every(commands: &[Vec<u8>]) -> Option<u8> {
+        commands
+            .iter()
+            .find(|sequence| sequence.starts_with(&[0x1B, 0x69, 0x41]))
+            .map(|sequence| sequence[3])
+    }
+
+    #[test]
+    fn cut_at_end() {
+        let commands = encode_job(PrinterModel::QL700, job(Cut::AtEnd)).unwrap();
+        assert_eq!(modes(&commands), (0x00, 0x08));
+        assert_eq!(cut_every(&commands), None);
+    }
+
+    #[test]
+    fn cut_every_pages() {
+        let commands = encode_job(PrinterModel::QL700, job(Cut::Every(2))).unwrap();
+        assert_eq!(modes(&commands), (0x40, 0x08));
+        assert_eq!(cut_every(&commands), Some(2));
+    }
+
+    #[test]
+    fn never_cut() {
+        let commands = encode_job(PrinterModel::QL700, job(Cut::Never)).unwrap();
+        assert_eq!(modes(&commands), (0x00, 0x00));
+        assert_eq!(cut_every(&commands), None);
+
+        let mut chained = job(Cut::AtEnd);
+        chained.chain_printing = true;
+        let commands = encode_job(PrinterModel::QL700, chained).unwrap();
+        assert_eq!(modes(&commands), (0x00, 0x00));
+    }
+
+    #[test]
+    fn feed_margin_minimum() {
+        let mut narrow = job(Cut::AtEnd);
+        narrow.feed_margin = Some(Margin::Dots(34));
+        assert!(encode_job(PrinterModel::QL700, narrow).is_err());
+
+        let mut minimum = job(Cut::AtEnd);
+        minimum.feed_margin = Some(Margin::Dots(MIN_FEED_MARGIN));
+        let commands = encode_job(PrinterModel::QL700, minimum).unwrap();
+        assert!(commands.contains(&vec![0x1B, 0x69, 0x64, 35, 0]));
+    }
+}
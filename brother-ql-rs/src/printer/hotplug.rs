@@ -0,0 +1,191 @@
+//! Notifications about Brother QL printers being attached and detached
+//!
+//! Uses libusb's hotplug support where the platform has it, and falls back to periodically
+//! scanning the USB bus otherwise.
+use std::collections::HashMap;
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use log::warn;
+use rusb::{GlobalContext, UsbContext};
+
+use crate::printer::{constants, discovered_device, known_device, DiscoveredDevice, Result};
+
+/// How often the bus is scanned when hotplug support isn't available
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+pub enum HotplugEvent {
+    Arrived(DiscoveredDevice<GlobalContext>),
+    Left(DiscoveredDevice<GlobalContext>),
+}
+
+/// Watches the USB bus in a background thread until dropped.
+///
+/// Printers that are already attached when the watcher is created are reported as arrived.
+pub struct HotplugWatcher {
+    events: Receiver<HotplugEvent>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl HotplugWatcher {
+    /// Watches using libusb's hotplug support if available, or by scanning the bus every second.
+    pub fn new() -> Result<HotplugWatcher> {
+        if rusb::has_hotplug() {
+            HotplugWatcher::spawn(watch_hotplug)
+        } else {
+            HotplugWatcher::polling(POLL_INTERVAL)
+        }
+    }
+
+    /// Watches by scanning the bus for printers every `interval`.
+    pub fn polling(interval: Duration) -> Result<HotplugWatcher> {
+        HotplugWatcher::spawn(move |events, stop| {
+            watch_polling(events, stop, interval);
+            Ok(())
+        })
+    }
+
+    fn spawn<F>(watch: F) -> Result<HotplugWatcher>
+    where
+        F: FnOnce(Sender<HotplugEvent>, Arc<AtomicBool>) -> Result<()> + Send + 'static,
+    {
+        let (sender, events) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread = thread::Builder::new()
+            .name("brother-ql-hotplug".to_string())
+            .spawn(move || {
+                if let Err(error) = watch(sender, thread_stop) {
+                    warn!("Stopped watching for printers: {}", error);
+                }
+            })?;
+        Ok(HotplugWatcher {
+            events,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Blocks until the next printer arrives or leaves.
+    pub fn recv(&self) -> Option<HotplugEvent> {
+        self.events.recv().ok()
+    }
+
+    /// Waits up to `timeout` for a printer to arrive or leave.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<HotplugEvent> {
+        self.events.recv_timeout(timeout).ok()
+    }
+
+    /// Returns an event if one is pending, without blocking.
+    pub fn try_recv(&self) -> Option<HotplugEvent> {
+        self.events.try_recv().ok()
+    }
+}
+
+impl Drop for HotplugWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Devices the hotplug callback couldn't identify by their product id
+///
+/// libusb doesn't allow communicating with devices from its hotplug callbacks, so they are probed
+/// after the events were handled, and remembered by bus number and address to report them leaving.
+#[derive(Default)]
+struct Unidentified {
+    /// Devices that arrived and still have to be probed
+    arrived: Vec<rusb::Device<GlobalContext>>,
+    /// Printers in Editor Lite mode found by probing
+    printers: HashMap<(u8, u8), DiscoveredDevice<GlobalContext>>,
+}
+
+fn bus_address(device: &rusb::Device<GlobalContext>) -> (u8, u8) {
+    (device.bus_number(), device.address())
+}
+
+struct Callback {
+    events: Sender<HotplugEvent>,
+    unidentified: Arc<Mutex<Unidentified>>,
+}
+
+impl rusb::Hotplug<GlobalContext> for Callback {
+    fn device_arrived(&mut self, device: rusb::Device<GlobalContext>) {
+        match known_device(&device) {
+            Some(discovered) => {
+                let _ = self.events.send(HotplugEvent::Arrived(discovered));
+            }
+            None => self.unidentified.lock().unwrap().arrived.push(device),
+        }
+    }
+
+    fn device_left(&mut self, device: rusb::Device<GlobalContext>) {
+        let key = bus_address(&device);
+        let mut unidentified = self.unidentified.lock().unwrap();
+        // Devices that leave before being probed were never reported
+        unidentified.arrived.retain(|arrived| bus_address(arrived) != key);
+        if let Some(discovered) = unidentified.printers.remove(&key).or_else(|| known_device(&device)) {
+            let _ = self.events.send(HotplugEvent::Left(discovered));
+        }
+    }
+}
+
+fn watch_hotplug(events: Sender<HotplugEvent>, stop: Arc<AtomicBool>) -> Result<()> {
+    let context = GlobalContext::default();
+    let unidentified = Arc::new(Mutex::new(Unidentified::default()));
+    let mut builder = rusb::HotplugBuilder::new();
+    builder.vendor_id(constants::VENDOR_ID).enumerate(true);
+    let callback = Callback {
+        events: events.clone(),
+        unidentified: unidentified.clone(),
+    };
+    let _registration = builder.register(context, Box::new(callback))?;
+    while !stop.load(Ordering::Relaxed) {
+        context.handle_events(Some(Duration::from_millis(200)))?;
+
+        // The callbacks only run within `handle_events`, so the devices can be probed unlocked
+        let arrived = mem::take(&mut unidentified.lock().unwrap().arrived);
+        for discovered in arrived.into_iter().filter_map(discovered_device) {
+            let key = bus_address(&discovered.device);
+            unidentified.lock().unwrap().printers.insert(key, discovered.clone());
+            let _ = events.send(HotplugEvent::Arrived(discovered));
+        }
+    }
+    Ok(())
+}
+
+fn watch_polling(events: Sender<HotplugEvent>, stop: Arc<AtomicBool>, interval: Duration) {
+    let mut attached: HashMap<(u8, u8), DiscoveredDevice<GlobalContext>> = HashMap::new();
+    while !stop.load(Ordering::Relaxed) {
+        let mut current = HashMap::new();
+        if let Ok(devices) = rusb::devices() {
+            for discovered in devices.iter().filter_map(discovered_device) {
+                let key = (discovered.device.bus_number(), discovered.device.address());
+                current.insert(key, discovered);
+            }
+        }
+
+        for (key, discovered) in attached.iter() {
+            if !current.contains_key(key) {
+                let _ = events.send(HotplugEvent::Left(discovered.clone()));
+            }
+        }
+        for (key, discovered) in current.iter() {
+            if !attached.contains_key(key) {
+                let _ = events.send(HotplugEvent::Arrived(discovered.clone()));
+            }
+        }
+        attached = current;
+
+        thread::sleep(interval);
+    }
+}
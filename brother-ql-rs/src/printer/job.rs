@@ -1,20 +1,159 @@
-use std::convert::TryInto;
-use crate::printer::constants::RASTER_LINE_LENGTH;
+use crate::printer::catalogue::{CatalogueEntry, Colors};
+use crate::printer::constants::{Label, MAX_PIXEL_WIDTH, RASTER_LINE_LENGTH};
+use crate::printer::model::PrinterModel;
+use crate::printer::preview::Preview;
 use crate::printer::setting::Resolution;
+use crate::printer::{Printable, Result};
 
+/// When the cutter should cut the tape
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Cut {
+    Never,
+    /// Cut after the last page of a job
+    AtEnd,
+    /// Cut after every given number of pages, from 1 to 255
+    Every(u8),
+}
+
+/// Blank tape fed before and after the content of a page on continuous tape
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Margin {
+    Dots(u16),
+    Millimeters(f32),
+}
+
+impl Margin {
+    pub fn to_dots(&self) -> u16 {
+        match self {
+            Margin::Dots(dots) => *dots,
+            // The print head has 300 dots per inch
+            Margin::Millimeters(mm) => (mm * 300.0 / 25.4).round() as u16,
+        }
+    }
+}
+
+/// A page to print and how to print it
+///
+/// Create jobs with `new`, `from_grayscale` or `from_label_page` and change their fields afterwards,
+/// as fields may be added in future versions. The `cut_on_end` flag of earlier versions is now
+/// `cut`: `Cut::AtEnd` for `true` and `Cut::Never` for `false`.
+#[derive(Clone)]
+#[non_exhaustive]
 pub struct PrintJob {
-    pub cut_on_end: bool,
+    pub cut: Cut,
+    /// Overrides the feed margin of the loaded label. Only possible on continuous tape.
+    pub feed_margin: Option<Margin>,
+    /// Don't feed and cut after the last page, so the next job starts right where this one ends
+    pub chain_printing: bool,
     pub raster_lines: Vec<[u8; RASTER_LINE_LENGTH]>,
     pub resolution: Resolution,
-    pub mirrored: bool
+    pub mirrored: bool,
+    /// Skip checking that the content fits onto the loaded label, for deliberately printing out of bounds
+    pub overprint: bool,
 }
 
 impl PrintJob {
+    /// Creates a job printing raster lines as they are, at `Resolution::Normal`, cut at the end and
+    /// with the feed margin of the loaded label.
+    pub fn new(raster_lines: Vec<[u8; RASTER_LINE_LENGTH]>) -> PrintJob {
+        PrintJob {
+            cut: Cut::AtEnd,
+            feed_margin: None,
+            chain_printing: false,
+            raster_lines,
+            resolution: Resolution::Normal,
+            mirrored: false,
+            overprint: false,
+        }
+    }
+
+    /// Creates a job printing a grayscale image with one byte per pixel, cut at the end.
+    ///
+    /// Pixels darker than mid-gray are printed. The image is placed on the left of the printable area
+    /// of `label` as seen when looking at the printed label, and mustn't be wider than it.
+    pub fn from_grayscale(pixels: &[u8], width: usize, label: &Label) -> Result<PrintJob> {
+        let printable = label.printable_dots();
+        if width == 0 || width > printable.len() {
+            bail!(
+                "Image is {} pixels wide, but the {}mm label is {} dots wide",
+                width,
+                label.tape_size.0,
+                printable.len()
+            );
+        }
+        // Mirrored lines are reversed before they are sent, see `get_raster_lines`
+        let offset = MAX_PIXEL_WIDTH - printable.end;
+        let raster_lines = pixels
+            .chunks(width)
+            .map(|row| {
+                let mut dots = [true; MAX_PIXEL_WIDTH];
+                for (x, &value) in row.iter().enumerate() {
+                    dots[offset + x] = value >= 0x80;
+                }
+                dots.into_raster_line()
+            })
+            .collect();
+        let mut job = PrintJob::new(raster_lines);
+        job.mirrored = true;
+        Ok(job)
+    }
+
+    /// Creates a job printing a grayscale page laid out for the whole label, cut at the end.
+    ///
+    /// Pages sent by desktop applications cover the whole label, including its unprintable margins.
+    /// As the printable area is centered on the label, pages larger than it are cropped evenly on both
+    /// sides, and at the top and bottom for die-cut labels. Smaller pages are placed like
+    /// `from_grayscale` places them. Every row of the page becomes one raster line at `resolution`.
+    pub fn from_label_page(
+        pixels: &[u8],
+        width: usize,
+        label: &Label,
+        resolution: Resolution,
+    ) -> Result<PrintJob> {
+        if width == 0 {
+            bail!("Page is empty");
+        }
+        let height = pixels.len() / width;
+        let cropped_width = width.min(label.dots_printable.0 as usize);
+        let cropped_height = match label.dots_printable.1 as usize {
+            0 => height,
+            length => height.min(length * resolution.lines_per_pixel()),
+        };
+        let left = (width - cropped_width) / 2;
+        let top = (height - cropped_height) / 2;
+        let cropped: Vec<u8> = pixels
+            .chunks(width)
+            .skip(top)
+            .take(cropped_height)
+            .flat_map(|row| row[left..left + cropped_width].iter().copied())
+            .collect();
+        let mut job = PrintJob::from_grayscale(&cropped, cropped_width, label)?;
+        job.resolution = resolution;
+        Ok(job)
+    }
+
+    /// Changes the resolution the job is printed at, keeping its aspect ratio by repeating or dropping
+    /// raster lines. Jobs created by `from_grayscale` have square pixels at `Resolution::Normal`, so
+    /// this prints them at `Resolution::High` with every line twice.
+    pub fn with_resolution(self, resolution: Resolution) -> PrintJob {
+        let square_pixels: Vec<_> = self
+            .raster_lines
+            .iter()
+            .step_by(self.resolution.lines_per_pixel())
+            .copied()
+            .collect();
+        PrintJob {
+            raster_lines: resolution.scale_lines(&square_pixels),
+            resolution,
+            ..self
+        }
+    }
+
     pub(crate) fn get_raster_lines(&self) -> Vec<[u8; RASTER_LINE_LENGTH]> {
         self.raster_lines.iter()
             .map(|&chunk|{
                 if self.mirrored {
-                    let mut data: [u8; RASTER_LINE_LENGTH] = chunk.try_into().unwrap();
+                    let mut data: [u8; RASTER_LINE_LENGTH] = chunk;
                     data.reverse();
                     data.map(|byte|{ byte.reverse_bits() })
                 } else {
@@ -23,4 +162,170 @@ impl PrintJob {
             })
             .collect()
     }
-}
\ No newline at end of file
+
+    /// Checks that the job can be printed on the given label.
+    ///
+    /// Unless `overprint` is set, this includes checking that no dots are printed outside of the
+    /// printable area of the label and that the job isn't longer than a die-cut label.
+    pub fn validate(&self, label: &Label) -> Result<()> {
+        if self.cut == Cut::Every(0) {
+            bail!("Cannot cut after every 0 pages");
+        }
+        self.feed_margin_dots(label)?;
+        if self.overprint {
+            return Ok(());
+        }
+
+        if label.is_die_cut() {
+            let max_lines = label.dots_printable.1 as usize * self.resolution.lines_per_pixel();
+            if self.raster_lines.len() > max_lines {
+                bail!(
+                    "Job is {} lines long, but the {}x{}mm label only fits {} lines",
+                    self.raster_lines.len(),
+                    label.tape_size.0,
+                    label.tape_size.1,
+                    max_lines
+                );
+            }
+        }
+
+        let printable = label.printable_dots();
+        for (index, line) in self.get_raster_lines().iter().enumerate() {
+            let outside = (0..MAX_PIXEL_WIDTH)
+                .filter(|dot| !printable.contains(dot))
+                .find(|&dot| line[dot / 8] & (0x80 >> (dot % 8)) != 0);
+            if let Some(dot) = outside {
+                bail!(
+                    "Line {} prints dot {}, outside of the printable dots {} to {} of the {}mm label",
+                    index + 1,
+                    dot,
+                    printable.start,
+                    printable.end - 1,
+                    label.tape_size.0
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Same as `validate()`, but also checks that the model can print on the media of a catalogue entry,
+    /// such as two-colour tape, which needs a two-colour printer.
+    ///
+    /// Jobs are always printed in black only, which two-colour printers can print on any media.
+    pub fn validate_media(&self, entry: &CatalogueEntry, model: &PrinterModel) -> Result<()> {
+        if !entry.supports_model(model) {
+            if entry.colors == Colors::BlackRed && !model.supports_two_color() {
+                bail!(
+                    "The {} can't print on {}, it needs a two-colour printer",
+                    model.to_str(),
+                    entry.description
+                );
+            }
+            bail!("The {} can't print on {}", model.to_str(), entry.description);
+        }
+        self.validate(&entry.label)
+    }
+
+    /// Returns the feed margin to use for the given label, checking that it can be used with it.
+    ///
+    /// The minimum margin depends on the model, which the encoder checks.
+    pub(crate) fn feed_margin_dots(&self, label: &Label) -> Result<u16> {
+        let margin = match self.feed_margin {
+            None => return Ok(label.feed_margin as u16),
+            Some(margin) => margin.to_dots(),
+        };
+        if label.is_die_cut() {
+            bail!("Feed margins can only be changed on continuous tape");
+        }
+        Ok(margin)
+    }
+
+    /// Renders the job the way it will come out of the printer.
+    ///
+    /// Pass the loaded label to have dots outside of its printable area marked in the preview.
+    pub fn to_image(&self, label: Option<&Label>) -> Preview {
+        Preview::new(self, label)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::catalogue;
+    use crate::printer::test_support::label;
+
+    #[test]
+    fn accepts_images_within_the_printable_area() {
+        let label = label("29x90");
+        let width = label.dots_printable.0 as usize;
+        let height = label.dots_printable.1 as usize;
+        let job = PrintJob::from_grayscale(&vec![0; width * height], width, &label).unwrap();
+        job.validate(&label).unwrap();
+    }
+
+    #[test]
+    fn rejects_jobs_longer_than_die_cut_labels() {
+        let label = label("29x90");
+        let width = label.dots_printable.0 as usize;
+        let height = label.dots_printable.1 as usize + 1;
+        let mut job = PrintJob::from_grayscale(&vec![0; width * height], width, &label).unwrap();
+        assert!(job.validate(&label).is_err());
+        // The same job fits on continuous tape
+        job.validate(&self::label("29")).unwrap();
+        job.overprint = true;
+        job.validate(&label).unwrap();
+    }
+
+    #[test]
+    fn rejects_dots_outside_of_the_printable_area() {
+        let label = label("62");
+        let mut dots = [true; MAX_PIXEL_WIDTH];
+        dots[label.printable_dots().start - 1] = false;
+        let mut job = PrintJob::from_grayscale(&[0xFF; 10], 10, &label).unwrap();
+        job.raster_lines = vec![dots.into_raster_line()];
+        job.mirrored = false;
+        assert!(job.validate(&label).is_err());
+        job.overprint = true;
+        job.validate(&label).unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_margins_and_cuts() {
+        let job = PrintJob::from_grayscale(&[0; 10], 10, &label("29x90")).unwrap();
+        let with_margin = PrintJob {
+            feed_margin: Some(Margin::Millimeters(5.0)),
+            ..job.clone()
+        };
+        assert!(with_margin.validate(&label("29x90")).is_err());
+        with_margin.validate(&label("29")).unwrap();
+        let cut_never = PrintJob {
+            cut: Cut::Every(0),
+            ..job
+        };
+        assert!(cut_never.validate(&label("29")).is_err());
+    }
+
+    #[test]
+    fn changing_the_resolution_keeps_the_aspect_ratio() {
+        let label = label("62");
+        let job = PrintJob::from_grayscale(&[0, 0xFF, 0xFF, 0], 2, &label).unwrap();
+        let high = job.clone().with_resolution(Resolution::High);
+        assert_eq!(high.resolution, Resolution::High);
+        assert_eq!(
+            high.raster_lines,
+            [job.raster_lines[0], job.raster_lines[0], job.raster_lines[1], job.raster_lines[1]]
+        );
+        let normal = high.with_resolution(Resolution::Normal);
+        assert_eq!(normal.resolution, Resolution::Normal);
+        assert_eq!(normal.raster_lines, job.raster_lines);
+    }
+
+    #[test]
+    fn rejects_two_color_media_on_single_color_printers() {
+        let entry = catalogue::find("62red").unwrap();
+        let job = PrintJob::from_grayscale(&[0; 10], 10, &entry.label).unwrap();
+        assert!(job.validate_media(entry, &PrinterModel::QL700).is_err());
+        job.validate_media(catalogue::find("62").unwrap(), &PrinterModel::QL700)
+            .unwrap();
+    }
+}
@@ -0,0 +1,388 @@
+//! A persistent record of printed jobs
+//!
+//! A `Journal` appends one line of JSON per job to a file: when and on which printer the job was
+//! printed, its size, the last status the printer reported and any error. Attach it to a printer with
+//! `ThermalPrinter::set_journal` to record every job printed through the printer, including jobs
+//! printed by a `Spooler`, `PrinterPool` or `Batch` using it. Entries are never changed once written.
+//!
+//! With `Journal::with_previews`, a PNG preview of the first page of every job is kept as well.
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::printer::job::PrintJob;
+use crate::printer::{status, Result, ResultExt};
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    /// The printer reported every page as printed
+    Completed,
+    /// The job was sent to the printer without waiting for it to be printed
+    Sent,
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Position of the entry in the journal, starting at 1
+    pub id: u64,
+    /// When the job was started and finished, in seconds since the Unix epoch
+    pub started: u64,
+    pub finished: u64,
+    pub serial_number: String,
+    pub model: String,
+    pub pages: usize,
+    pub raster_lines: usize,
+    pub outcome: Outcome,
+    /// The last status the printer reported, serialized like `status::Response`
+    pub status: Option<serde_json::Value>,
+    pub error: Option<String>,
+    /// File name of the preview in the preview directory
+    pub preview: Option<String>,
+}
+
+/// Which entries `Journal::query` returns. Every condition that is set has to match.
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    pub serial_number: Option<String>,
+    /// Only jobs started at or after this time, in seconds since the Unix epoch
+    pub since: Option<u64>,
+    /// Only jobs started before this time, in seconds since the Unix epoch
+    pub until: Option<u64>,
+    pub outcome: Option<Outcome>,
+    /// Return at most this many entries, the most recent ones
+    pub limit: Option<usize>,
+}
+
+impl Query {
+    fn matches(&self, entry: &JournalEntry) -> bool {
+        self.serial_number.as_ref().is_none_or(|serial| *serial == entry.serial_number)
+            && self.since.is_none_or(|since| entry.started >= since)
+            && self.until.is_none_or(|until| entry.started < until)
+            && self.outcome.is_none_or(|outcome| outcome == entry.outcome)
+    }
+}
+
+/// Details of a job to record, see `Journal::record`
+pub struct JobRecord<'a> {
+    pub serial_number: &'a str,
+    pub model: &'a str,
+    pub jobs: &'a [PrintJob],
+    pub started: SystemTime,
+    pub outcome: Outcome,
+    pub result: &'a Result<status::Response>,
+}
+
+/// An append-only file of `JournalEntry` lines, safe to share between printers
+pub struct Journal {
+    path: PathBuf,
+    previews: Option<PathBuf>,
+    /// The open file and the id of the next entry
+    file: Mutex<(File, u64)>,
+}
+
+impl Journal {
+    /// Opens the journal at `path`, creating it if it doesn't exist yet.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Journal> {
+        let path = path.as_ref().to_path_buf();
+        remove_incomplete_entry(&path)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .chain_err(|| format!("Can't open journal {}", path.display()))?;
+        let next_id = read_entries(&path)?.last().map_or(1, |entry| entry.id + 1);
+        Ok(Journal {
+            path,
+            previews: None,
+            file: Mutex::new((file, next_id)),
+        })
+    }
+
+    /// Same as `open()`, but also writes a PNG preview of the first page of every job to `directory`.
+    pub fn with_previews<P: AsRef<Path>, D: AsRef<Path>>(path: P, directory: D) -> Result<Journal> {
+        let directory = directory.as_ref().to_path_buf();
+        fs::create_dir_all(&directory)?;
+        let mut journal = Journal::open(path)?;
+        journal.previews = Some(directory);
+        Ok(journal)
+    }
+
+    /// Appends an entry for a job, returning it.
+    pub fn record(&self, record: JobRecord) -> Result<JournalEntry> {
+        let mut file = self.file.lock().unwrap();
+        let id = file.1;
+
+        let preview = match (&self.previews, record.jobs.first()) {
+            (Some(directory), Some(job)) if !job.raster_lines.is_empty() => {
+                let name = format!("{}.png", id);
+                match job.to_image(None).write_png(directory.join(&name)) {
+                    Ok(()) => Some(name),
+                    Err(error) => {
+                        warn!("Can't write preview of journal entry {}: {}", id, error);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+        let (outcome, status, error) = match record.result {
+            Ok(status) => (record.outcome, Some(serde_json::to_value(status)?), None),
+            Err(error) => (Outcome::Failed, None, Some(error.to_string())),
+        };
+        let entry = JournalEntry {
+            id,
+            started: seconds(record.started),
+            finished: seconds(SystemTime::now()),
+            serial_number: record.serial_number.to_string(),
+            model: record.model.to_string(),
+            pages: record.jobs.len(),
+            raster_lines: record.jobs.iter().map(|job| job.raster_lines.len()).sum(),
+            outcome,
+            status,
+            error,
+            preview,
+        };
+
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        file.0.write_all(line.as_bytes())?;
+        file.0.flush()?;
+        file.1 += 1;
+        Ok(entry)
+    }
+
+    /// Every entry, oldest first
+    pub fn entries(&self) -> Result<Vec<JournalEntry>> {
+        // Wait for entries being written
+        let _file = self.file.lock().unwrap();
+        read_entries(&self.path)
+    }
+
+    /// Entries matching `query`, oldest first
+    pub fn query(&self, query: &Query) -> Result<Vec<JournalEntry>> {
+        let mut entries: Vec<JournalEntry> = self
+            .entries()?
+            .into_iter()
+            .filter(|entry| query.matches(entry))
+            .collect();
+        if let Some(limit) = query.limit {
+            entries.drain(..entries.len().saturating_sub(limit));
+        }
+        Ok(entries)
+    }
+
+    /// Path of the preview of an entry, if one was written
+    pub fn preview_path(&self, entry: &JournalEntry) -> Option<PathBuf> {
+        Some(self.previews.as_ref()?.join(entry.preview.as_ref()?))
+    }
+}
+
+/// Reads the entries of a journal. A last line without a line break is an entry that is incomplete,
+/// as every entry is written along with its line break, so it is ignored.
+fn read_entries(path: &Path) -> Result<Vec<JournalEntry>> {
+    let mut entries = Vec::new();
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut line = Vec::new();
+    for index in 1.. {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 || !line.ends_with(b"\n") {
+            break;
+        }
+        if line.trim_ascii().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_slice(&line).chain_err(|| {
+            format!("Line {} of journal {} is invalid", index, path.display())
+        })?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Cuts off the incomplete entry a process stopped while writing it leaves at the end of a journal,
+/// so entries appended afterwards start on a line of their own.
+fn remove_incomplete_entry(path: &Path) -> Result<()> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error.into()),
+    };
+    if data.is_empty() || data.ends_with(b"\n") {
+        return Ok(());
+    }
+    let end = data.iter().rposition(|&byte| byte == b'\n').map_or(0, |index| index + 1);
+    warn!(
+        "Removing the incomplete last entry of journal {}: {}",
+        path.display(),
+        String::from_utf8_lossy(&data[end..])
+    );
+    OpenOptions::new().write(true).open(path)?.set_len(end as u64)?;
+    Ok(())
+}
+
+fn seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::model::PrinterModel;
+    use crate::printer::status_type::StatusType;
+    use crate::printer::test_support::{label, temp_path};
+    use crate::printer::ErrorKind;
+
+    /// A journal file of its own for every test, removed beforehand with its previews
+    fn path(name: &str) -> PathBuf {
+        let path = temp_path("journal", name);
+        let _ = fs::remove_dir_all(path.with_extension("previews"));
+        path
+    }
+
+    fn completed() -> Result<status::Response> {
+        let label = label("62");
+        Ok(status::Response {
+            model: PrinterModel::QL700,
+            status_type: StatusType::PrintingCompleted,
+            errors: Vec::new(),
+            media: status::Media::from_label(&label),
+        })
+    }
+
+    fn record(journal: &Journal, serial_number: &str, result: &Result<status::Response>) -> JournalEntry {
+        let label = label("62");
+        let job = PrintJob::from_grayscale(&[0; 20], 10, &label).unwrap();
+        journal
+            .record(JobRecord {
+                serial_number,
+                model: "QL-700",
+                jobs: &[job.clone(), job],
+                started: SystemTime::now(),
+                outcome: Outcome::Completed,
+                result,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn records_jobs() {
+        let path = path("records");
+        let journal = Journal::open(&path).unwrap();
+        let entry = record(&journal, "A", &completed());
+        assert_eq!(entry.id, 1);
+        assert_eq!((entry.pages, entry.raster_lines), (2, 4));
+        assert_eq!(entry.outcome, Outcome::Completed);
+        assert!(entry.status.is_some() && entry.error.is_none() && entry.preview.is_none());
+
+        let failed = record(&journal, "A", &Err(ErrorKind::Disconnected.into()));
+        assert_eq!(failed.id, 2);
+        assert_eq!(failed.outcome, Outcome::Failed);
+        assert!(failed.status.is_none() && failed.error.is_some());
+        assert_eq!(journal.entries().unwrap(), [entry, failed]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn queries_entries() {
+        let path = path("queries");
+        let journal = Journal::open(&path).unwrap();
+        for serial_number in ["A", "B", "A", "A"] {
+            record(&journal, serial_number, &completed());
+        }
+        record(&journal, "A", &Err(ErrorKind::Disconnected.into()));
+        let ids = |query: Query| -> Vec<u64> {
+            journal.query(&query).unwrap().iter().map(|entry| entry.id).collect()
+        };
+
+        assert_eq!(ids(Query::default()), [1, 2, 3, 4, 5]);
+        let printer_a = Query {
+            serial_number: Some("A".to_string()),
+            ..Query::default()
+        };
+        assert_eq!(ids(printer_a.clone()), [1, 3, 4, 5]);
+        assert_eq!(
+            ids(Query {
+                limit: Some(2),
+                ..printer_a.clone()
+            }),
+            [4, 5]
+        );
+        assert_eq!(
+            ids(Query {
+                outcome: Some(Outcome::Completed),
+                limit: Some(2),
+                ..printer_a
+            }),
+            [3, 4]
+        );
+        assert_eq!(
+            ids(Query {
+                outcome: Some(Outcome::Failed),
+                ..Query::default()
+            }),
+            [5]
+        );
+        assert!(ids(Query {
+            until: Some(0),
+            ..Query::default()
+        })
+        .is_empty());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn continues_ids_when_reopened() {
+        let path = path("reopened");
+        let journal = Journal::open(&path).unwrap();
+        record(&journal, "A", &completed());
+        record(&journal, "A", &completed());
+        drop(journal);
+
+        let journal = Journal::open(&path).unwrap();
+        assert_eq!(record(&journal, "A", &completed()).id, 3);
+        assert_eq!(journal.entries().unwrap().len(), 3);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn removes_incomplete_last_entry() {
+        let path = path("incomplete");
+        let journal = Journal::open(&path).unwrap();
+        record(&journal, "A", &completed());
+        record(&journal, "A", &completed());
+        drop(journal);
+        // Stopped while writing the second entry
+        let data = fs::read(&path).unwrap();
+        fs::write(&path, &data[..data.len() - 20]).unwrap();
+
+        let journal = Journal::open(&path).unwrap();
+        assert_eq!(journal.entries().unwrap().len(), 1);
+        assert_eq!(record(&journal, "A", &completed()).id, 2);
+        let ids: Vec<u64> = journal.entries().unwrap().iter().map(|entry| entry.id).collect();
+        assert_eq!(ids, [1, 2]);
+
+        // Invalid complete lines are still errors
+        fs::write(&path, "{}\n").unwrap();
+        assert!(Journal::open(&path).is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn writes_previews() {
+        let path = path("previews");
+        let directory = path.with_extension("previews");
+        let journal = Journal::with_previews(&path, &directory).unwrap();
+        let entry = record(&journal, "A", &completed());
+        assert_eq!(entry.preview.as_deref(), Some("1.png"));
+        assert!(journal.preview_path(&entry).unwrap().is_file());
+        fs::remove_file(path).unwrap();
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -1,5 +1,6 @@
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum MediaType {
     None,
     ContinuousTape,
@@ -0,0 +1,408 @@
+//! Prometheus metrics of printers
+//!
+//! Printers update the metrics of their serial number as they print and report their status, and
+//! `render()` returns the metrics of all printers in the Prometheus text format, ready to be scraped:
+//!
+//! * `brother_ql_labels_printed_total`: pages the printer reported as printed
+//! * `brother_ql_raster_lines_sent_total` and `brother_ql_bytes_sent_total`
+//! * `brother_ql_printer_errors_total`: errors the printer reported, by the `error` message of
+//!   `status::Response::errors`
+//! * `brother_ql_job_duration_seconds`: how long jobs that were awaited took until their last page was
+//!   printed
+//! * `brother_ql_media_width_millimeters` and `brother_ql_media_length_millimeters`: the loaded media,
+//!   `0` if there is none and for the length of continuous tape
+//! * `brother_ql_printer_state`: `1` for the current `PrinterState`, `0` for the others
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::printer::status;
+use crate::printer::status_type::StatusType;
+
+/// Upper bounds of the job duration histogram buckets, in seconds
+const DURATION_BUCKETS: [f64; 8] = [0.5, 1.0, 2.0, 5.0, 10.0, 30.0, 60.0, 120.0];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PrinterState {
+    Ready,
+    Printing,
+    /// The printer reported an error, such as running out of tape or a cutter jam
+    Error,
+    Disconnected,
+}
+
+impl PrinterState {
+    const ALL: [PrinterState; 4] = [
+        PrinterState::Ready,
+        PrinterState::Printing,
+        PrinterState::Error,
+        PrinterState::Disconnected,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            PrinterState::Ready => "ready",
+            PrinterState::Printing => "printing",
+            PrinterState::Error => "error",
+            PrinterState::Disconnected => "disconnected",
+        }
+    }
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; DURATION_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+#[derive(Default)]
+struct PrinterMetrics {
+    labels_printed: u64,
+    raster_lines_sent: u64,
+    bytes_sent: u64,
+    errors: BTreeMap<&'static str, u64>,
+    job_durations: Histogram,
+    media_width: u8,
+    media_length: u8,
+    state: Option<PrinterState>,
+}
+
+/// Metrics of every printer, by serial number
+static METRICS: Mutex<BTreeMap<String, PrinterMetrics>> = Mutex::new(BTreeMap::new());
+
+fn update<F: FnOnce(&mut PrinterMetrics)>(serial_number: &str, update: F) {
+    let mut metrics = METRICS.lock().unwrap();
+    update(metrics.entry(serial_number.to_string()).or_default());
+}
+
+pub(crate) fn bytes_sent(serial_number: &str, bytes: usize) {
+    update(serial_number, |metrics| metrics.bytes_sent += bytes as u64);
+}
+
+pub(crate) fn job_started(serial_number: &str, raster_lines: usize) {
+    update(serial_number, |metrics| {
+        metrics.raster_lines_sent += raster_lines as u64;
+        metrics.state = Some(PrinterState::Printing);
+    });
+}
+
+pub(crate) fn job_finished(serial_number: &str, duration: Duration) {
+    update(serial_number, |metrics| {
+        let seconds = duration.as_secs_f64();
+        let histogram = &mut metrics.job_durations;
+        for (bucket, bound) in histogram.buckets.iter_mut().zip(DURATION_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        histogram.count += 1;
+        histogram.sum += seconds;
+    });
+}
+
+pub(crate) fn disconnected(serial_number: &str) {
+    update(serial_number, |metrics| {
+        metrics.state = Some(PrinterState::Disconnected)
+    });
+}
+
+/// Updates the metrics with a status message received from the printer.
+///
+/// Errors are counted when the printer reports them as they occur, not every time it is asked for its
+/// status.
+pub(crate) fn status_received(serial_number: &str, response: &status::Response) {
+    update(serial_number, |metrics| {
+        metrics.media_width = response.media.width;
+        metrics.media_length = response.media.length;
+        match response.status_type {
+            StatusType::PrintingCompleted => {
+                metrics.labels_printed += 1;
+                metrics.state = Some(PrinterState::Ready);
+            }
+            StatusType::ErrorOccurred => {
+                for error in &response.errors {
+                    *metrics.errors.entry(error).or_default() += 1;
+                }
+                metrics.state = Some(PrinterState::Error);
+            }
+            _ if !response.errors.is_empty() => metrics.state = Some(PrinterState::Error),
+            StatusType::ReplyToStatusRequest if metrics.state != Some(PrinterState::Printing) => {
+                metrics.state = Some(PrinterState::Ready)
+            }
+            _ => {}
+        }
+    });
+}
+
+/// Renders the metrics of all printers in the Prometheus text exposition format.
+pub fn render() -> String {
+    let metrics = METRICS.lock().unwrap();
+    let mut output = String::new();
+
+    type Value = fn(&PrinterMetrics) -> u64;
+    let values: [(&str, &str, &str, Value); 5] = [
+        (
+            "brother_ql_labels_printed_total",
+            "counter",
+            "Labels the printer reported as printed.",
+            |printer| printer.labels_printed,
+        ),
+        (
+            "brother_ql_raster_lines_sent_total",
+            "counter",
+            "Raster lines sent to the printer.",
+            |printer| printer.raster_lines_sent,
+        ),
+        (
+            "brother_ql_bytes_sent_total",
+            "counter",
+            "Bytes sent to the printer.",
+            |printer| printer.bytes_sent,
+        ),
+        (
+            "brother_ql_media_width_millimeters",
+            "gauge",
+            "Width of the loaded media.",
+            |printer| printer.media_width as u64,
+        ),
+        (
+            "brother_ql_media_length_millimeters",
+            "gauge",
+            "Length of the loaded labels, 0 for continuous tape.",
+            |printer| printer.media_length as u64,
+        ),
+    ];
+    for (name, kind, help, value) in values {
+        header(&mut output, name, kind, help);
+        for (serial_number, printer) in metrics.iter() {
+            sample(&mut output, name, serial_number, "", value(printer));
+        }
+    }
+
+    let name = "brother_ql_printer_errors_total";
+    header(
+        &mut output,
+        name,
+        "counter",
+        "Errors reported by the printer.",
+    );
+    for (serial_number, printer) in metrics.iter() {
+        for (error, count) in &printer.errors {
+            let labels = format!(",error=\"{}\"", escape(error));
+            sample(&mut output, name, serial_number, &labels, *count);
+        }
+    }
+
+    let name = "brother_ql_job_duration_seconds";
+    header(
+        &mut output,
+        name,
+        "histogram",
+        "Time from sending a job until its last page was printed.",
+    );
+    for (serial_number, printer) in metrics.iter() {
+        let histogram = &printer.job_durations;
+        let bucket = format!("{}_bucket", name);
+        for (count, bound) in histogram.buckets.iter().zip(DURATION_BUCKETS) {
+            let labels = format!(",le=\"{}\"", bound);
+            sample(&mut output, &bucket, serial_number, &labels, *count);
+        }
+        sample(
+            &mut output,
+            &bucket,
+            serial_number,
+            ",le=\"+Inf\"",
+            histogram.count,
+        );
+        let _ = writeln!(
+            output,
+            "{}_sum{{serial_number=\"{}\"}} {}",
+            name,
+            escape(serial_number),
+            histogram.sum
+        );
+        sample(
+            &mut output,
+            &format!("{}_count", name),
+            serial_number,
+            "",
+            histogram.count,
+        );
+    }
+
+    let name = "brother_ql_printer_state";
+    header(&mut output, name, "gauge", "Current state of the printer.");
+    for (serial_number, printer) in metrics.iter() {
+        if let Some(current) = printer.state {
+            for state in PrinterState::ALL {
+                let labels = format!(",state=\"{}\"", state.name());
+                sample(
+                    &mut output,
+                    name,
+                    serial_number,
+                    &labels,
+                    (state == current) as u64,
+                );
+            }
+        }
+    }
+    output
+}
+
+fn header(output: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(output, "# HELP {} {}", name, help);
+    let _ = writeln!(output, "# TYPE {} {}", name, kind);
+}
+
+/// Writes a sample of a printer, `labels` being any further labels, each starting with a comma.
+fn sample(output: &mut String, name: &str, serial_number: &str, labels: &str, value: u64) {
+    let _ = writeln!(
+        output,
+        "{}{{serial_number=\"{}\"{}}} {}",
+        name,
+        escape(serial_number),
+        labels,
+        value
+    );
+}
+
+/// Escapes a label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lines of the rendered metrics of one printer. The metrics are shared by all tests, so every
+    /// test uses a serial number of its own.
+    fn samples(serial_number: &str) -> Vec<String> {
+        let label = format!("serial_number=\"{}\"", escape(serial_number));
+        render()
+            .lines()
+            .filter(|line| line.contains(&label))
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn status(status_type: u8, errors: u8) -> status::Response {
+        let mut message = [0; status::RESPONSE_SIZE];
+        message[0] = 0x80;
+        message[8] = errors;
+        message[10] = 62;
+        message[17] = 29;
+        message[18] = status_type;
+        status::Response::from_bytes(&message).unwrap()
+    }
+
+    #[test]
+    fn renders_counters_and_media() {
+        bytes_sent("counters", 100);
+        bytes_sent("counters", 28);
+        job_started("counters", 271);
+        status_received("counters", &status(1, 0));
+        status_received("counters", &status(2, 0x02));
+        let samples = samples("counters");
+        for expected in [
+            "brother_ql_labels_printed_total{serial_number=\"counters\"} 1",
+            "brother_ql_raster_lines_sent_total{serial_number=\"counters\"} 271",
+            "brother_ql_bytes_sent_total{serial_number=\"counters\"} 128",
+            "brother_ql_media_width_millimeters{serial_number=\"counters\"} 62",
+            "brother_ql_media_length_millimeters{serial_number=\"counters\"} 29",
+            "brother_ql_printer_errors_total{serial_number=\"counters\",error=\"End of media\"} 1",
+        ] {
+            assert!(
+                samples.iter().any(|line| line == expected),
+                "{} missing",
+                expected
+            );
+        }
+
+        let output = render();
+        assert!(output.contains("# TYPE brother_ql_labels_printed_total counter\n"));
+        assert!(output.contains("# TYPE brother_ql_job_duration_seconds histogram\n"));
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        bytes_sent("a\"b\\c\nd", 1);
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+        assert!(samples("a\"b\\c\nd").contains(
+            &"brother_ql_bytes_sent_total{serial_number=\"a\\\"b\\\\c\\nd\"} 1".to_string()
+        ));
+    }
+
+    #[test]
+    fn accumulates_histogram_buckets() {
+        job_finished("histogram", Duration::from_millis(800));
+        job_finished("histogram", Duration::from_secs(3));
+        job_finished("histogram", Duration::from_secs(500));
+        let samples = samples("histogram");
+        let bucket = |bound: &str| {
+            let prefix = format!(
+                "brother_ql_job_duration_seconds_bucket{{serial_number=\"histogram\",le=\"{}\"}} ",
+                bound
+            );
+            samples
+                .iter()
+                .find_map(|line| line.strip_prefix(&prefix))
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(bucket("0.5"), "0");
+        assert_eq!(bucket("1"), "1");
+        assert_eq!(bucket("2"), "1");
+        assert_eq!(bucket("5"), "2");
+        assert_eq!(bucket("120"), "2");
+        assert_eq!(bucket("+Inf"), "3");
+        assert!(samples.contains(
+            &"brother_ql_job_duration_seconds_sum{serial_number=\"histogram\"} 503.8".to_string()
+        ));
+        assert!(samples.contains(
+            &"brother_ql_job_duration_seconds_count{serial_number=\"histogram\"} 3".to_string()
+        ));
+    }
+
+    #[test]
+    fn sets_one_state_gauge() {
+        let states = || -> Vec<String> {
+            samples("state")
+                .into_iter()
+                .filter(|line| line.starts_with("brother_ql_printer_state{"))
+                .collect()
+        };
+        // No state before anything is known about the printer
+        bytes_sent("state", 1);
+        assert!(states().is_empty());
+
+        job_started("state", 10);
+        assert_eq!(
+            states(),
+            [
+                "brother_ql_printer_state{serial_number=\"state\",state=\"ready\"} 0",
+                "brother_ql_printer_state{serial_number=\"state\",state=\"printing\"} 1",
+                "brother_ql_printer_state{serial_number=\"state\",state=\"error\"} 0",
+                "brother_ql_printer_state{serial_number=\"state\",state=\"disconnected\"} 0",
+            ]
+        );
+        // Replies to status requests while printing don't end the job
+        status_received("state", &status(0, 0));
+        assert!(states()[1].ends_with("printing\"} 1"));
+        status_received("state", &status(1, 0));
+        assert!(states()[0].ends_with("ready\"} 1"));
+        status_received("state", &status(2, 0x01));
+        assert!(states()[2].ends_with("error\"} 1"));
+        disconnected("state");
+        assert!(states()[3].ends_with("disconnected\"} 1"));
+        assert_eq!(
+            states().iter().filter(|line| line.ends_with(" 1")).count(),
+            1
+        );
+    }
+}
@@ -1,5 +1,6 @@
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum PrinterModel {
     QL500O550,
     QL560,
@@ -28,6 +29,21 @@ impl PrinterModel {
         }
     }
 
+    /// Looks up a model by its name, such as `QL-700`, ignoring case.
+    pub fn from_name(name: &str) -> PrinterModel {
+        match name.to_ascii_uppercase().as_str() {
+            "QL-500" | "QL-550" | "QL-500/550" => PrinterModel::QL500O550,
+            "QL-560" => PrinterModel::QL560,
+            "QL-570" => PrinterModel::QL570,
+            "QL-580N" => PrinterModel::QL580N,
+            "QL-650TD" => PrinterModel::QL650T,
+            "QL-700" => PrinterModel::QL700,
+            "QL-1050" => PrinterModel::QL1050,
+            "QL-1060N" => PrinterModel::QL1060N,
+            _ => PrinterModel::Unknown,
+        }
+    }
+
     pub fn to_str(&self) -> &'static str {
         match self {
             PrinterModel::QL500O550 => "QL-500/550",
@@ -41,4 +57,24 @@ impl PrinterModel {
             PrinterModel::Unknown => "Unknown"
         }
     }
-}
\ No newline at end of file
+
+    /// Whether the model can print at 300 x 600 dpi, see `setting::Resolution::High`
+    pub fn supports_high_resolution(&self) -> bool {
+        !matches!(
+            self,
+            PrinterModel::QL500O550 | PrinterModel::QL560 | PrinterModel::QL650T
+        )
+    }
+
+    /// Whether the model can print black and red on two-colour media. Only the QL-800 series can, which
+    /// isn't among the supported models yet.
+    pub fn supports_two_color(&self) -> bool {
+        false
+    }
+
+    /// Whether the model supports the auto power on and auto power off settings. Of the supported
+    /// models, only the command reference of the QL-700 lists `ESC i U`.
+    pub fn supports_power_settings(&self) -> bool {
+        matches!(self, PrinterModel::QL700)
+    }
+}
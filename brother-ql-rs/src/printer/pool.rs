@@ -0,0 +1,109 @@
+//! Printing to whichever of several printers has the right labels loaded
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, TryLockError};
+
+use log::{debug, warn};
+
+use crate::printer::constants::Label;
+use crate::printer::job::PrintJob;
+use crate::printer::{Result, ThermalPrinter};
+
+/// A group of printers that jobs are routed to by the label they need.
+///
+/// Before printing, the loaded media of a candidate printer is checked, and printers reporting errors
+/// are skipped. Jobs for the same label are spread over all printers that have it loaded, preferring
+/// printers that aren't busy with another job.
+pub struct PrinterPool<T: rusb::UsbContext> {
+    printers: Vec<Mutex<ThermalPrinter<T>>>,
+    next: AtomicUsize,
+}
+
+impl<T: rusb::UsbContext> PrinterPool<T> {
+    pub fn new(printers: Vec<ThermalPrinter<T>>) -> PrinterPool<T> {
+        PrinterPool {
+            printers: printers.into_iter().map(Mutex::new).collect(),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.printers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.printers.is_empty()
+    }
+
+    /// Prints a single page on `label`, see `print_pages`.
+    pub fn print(&self, label: &Label, job: &PrintJob) -> Result<String> {
+        self.print_pages(label, std::slice::from_ref(job))
+    }
+
+    /// Prints several pages on a printer that has `label` loaded and isn't reporting errors, and waits
+    /// until they are printed.
+    ///
+    /// Labels are matched by their tape size. Returns the serial number of the printer that was used.
+    /// Printers busy with another job are only waited for if no idle printer has the label loaded.
+    pub fn print_pages(&self, label: &Label, jobs: &[PrintJob]) -> Result<String> {
+        if self.printers.is_empty() {
+            bail!("The printer pool is empty");
+        }
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let mut busy = Vec::new();
+        for index in 0..self.printers.len() {
+            let index = (start + index) % self.printers.len();
+            match self.printers[index].try_lock() {
+                Ok(printer) => {
+                    if let Some(serial_number) = print_if_ready(printer, label, jobs)? {
+                        return Ok(serial_number);
+                    }
+                }
+                Err(TryLockError::WouldBlock) => busy.push(index),
+                Err(TryLockError::Poisoned(error)) => {
+                    if let Some(serial_number) = print_if_ready(error.into_inner(), label, jobs)? {
+                        return Ok(serial_number);
+                    }
+                }
+            }
+        }
+        for index in busy {
+            let printer = self.printers[index]
+                .lock()
+                .unwrap_or_else(|error| error.into_inner());
+            if let Some(serial_number) = print_if_ready(printer, label, jobs)? {
+                return Ok(serial_number);
+            }
+        }
+        bail!(
+            "No printer with {}x{}mm labels is ready",
+            label.tape_size.0,
+            label.tape_size.1
+        )
+    }
+}
+
+/// Prints the pages if the printer has the label loaded and no errors, returning its serial number.
+fn print_if_ready<T: rusb::UsbContext>(
+    printer: MutexGuard<ThermalPrinter<T>>,
+    label: &Label,
+    jobs: &[PrintJob],
+) -> Result<Option<String>> {
+    let status = match printer.get_status() {
+        Ok(status) => status,
+        Err(error) => {
+            warn!("Skipping {:?}: {}", *printer, error);
+            return Ok(None);
+        }
+    };
+    if !status.errors.is_empty() {
+        debug!("Skipping {:?}: {}", *printer, status.errors.join(", "));
+        return Ok(None);
+    }
+    match status.media.label() {
+        Some(loaded) if loaded.tape_size == label.tape_size => {}
+        _ => return Ok(None),
+    }
+    debug!("Printing on {:?}", *printer);
+    printer.print_pages_blocking(jobs)?;
+    Ok(Some(printer.serial_number.clone()))
+}
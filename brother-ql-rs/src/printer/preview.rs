@@ -0,0 +1,159 @@
+//! Previews of print jobs, as they will come out of the printer
+//!
+//! Previews are rendered from the raster lines that are actually sent to the printer, so they
+//! reflect mirroring and the position of the content on the print head. When the loaded label is
+//! known, pixels outside of its printable area are marked so out-of-bounds content stands out.
+use crate::printer::constants::{Label, MAX_PIXEL_WIDTH};
+use crate::printer::job::PrintJob;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Pixel {
+    /// A dot that will be printed on the label
+    Black,
+    /// A dot inside the printable area that stays blank
+    White,
+    /// A blank dot on the label, but outside of its printable area
+    Margin,
+    /// A blank dot that isn't on the label at all
+    OffLabel,
+    /// A dot outside of the printable area that the job tries to print anyway
+    OutOfBounds,
+}
+
+impl Pixel {
+    pub fn is_printed(&self) -> bool {
+        matches!(self, Pixel::Black | Pixel::OutOfBounds)
+    }
+
+    fn rgb(&self) -> [u8; 3] {
+        match self {
+            Pixel::Black => [0x00, 0x00, 0x00],
+            Pixel::White => [0xFF, 0xFF, 0xFF],
+            Pixel::Margin => [0xDD, 0xDD, 0xDD],
+            Pixel::OffLabel => [0x80, 0x80, 0x80],
+            Pixel::OutOfBounds => [0xFF, 0x00, 0x00],
+        }
+    }
+}
+
+/// A rendered print job with square pixels, as seen when looking at the printed label
+///
+/// High resolution jobs print lines at half the height of normal ones, so each dot is repeated
+/// horizontally to keep the aspect ratio.
+pub struct Preview {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Pixel>,
+}
+
+impl Preview {
+    pub(crate) fn new(job: &PrintJob, label: Option<&Label>) -> Preview {
+        let raster_lines = job.get_raster_lines();
+        let scale = job.resolution.lines_per_pixel();
+
+        // Area of the print head covered by the label and its printable part, in dots from the
+        // start of a raster line. Lengths of continuous labels are unbounded.
+        let bounds = label.map(|label| {
+            let printable = label.printable_dots();
+            let side_margin = (label.dots.0 - label.dots_printable.0) as usize / 2;
+            let tape = (
+                printable.start.saturating_sub(side_margin),
+                printable.end + side_margin,
+            );
+            let printable_length = match label.dots_printable.1 {
+                0 => usize::MAX,
+                length => length as usize * scale,
+            };
+            (tape, printable, printable_length)
+        });
+
+        let width = MAX_PIXEL_WIDTH * scale;
+        let mut pixels = Vec::with_capacity(width * raster_lines.len());
+        for (y, line) in raster_lines.iter().enumerate() {
+            for x in 0..width {
+                // The first dot of a raster line ends up on the right edge of the label
+                let dot = MAX_PIXEL_WIDTH - 1 - x / scale;
+                let printed = line[dot / 8] & (0x80 >> (dot % 8)) != 0;
+                let pixel = match bounds {
+                    None => {
+                        if printed {
+                            Pixel::Black
+                        } else {
+                            Pixel::White
+                        }
+                    }
+                    Some((tape, ref printable, length)) => {
+                        let on_tape = dot >= tape.0 && dot < tape.1;
+                        let in_bounds = printable.contains(&dot) && y < length;
+                        match (printed, in_bounds, on_tape) {
+                            (true, true, _) => Pixel::Black,
+                            (true, false, _) => Pixel::OutOfBounds,
+                            (false, true, _) => Pixel::White,
+                            (false, false, true) => Pixel::Margin,
+                            (false, false, false) => Pixel::OffLabel,
+                        }
+                    }
+                };
+                pixels.push(pixel);
+            }
+        }
+
+        Preview {
+            width,
+            height: raster_lines.len(),
+            pixels,
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Pixel {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Returns the preview as 8-bit RGB data, row by row.
+    pub fn to_rgb(&self) -> Vec<u8> {
+        self.pixels.iter().flat_map(|pixel| pixel.rgb()).collect()
+    }
+
+    /// Renders the preview with Unicode half block characters, scaled down to fit into `columns`
+    /// characters. Every character covers two rows of square cells.
+    pub fn to_terminal(&self, columns: usize) -> String {
+        let cell = self.width.div_ceil(columns.max(1)).max(1);
+        let printed = |cell_x: usize, cell_y: usize| {
+            (cell_y * cell..((cell_y + 1) * cell).min(self.height)).any(|y| {
+                (cell_x * cell..((cell_x + 1) * cell).min(self.width))
+                    .any(|x| self.pixel(x, y).is_printed())
+            })
+        };
+
+        let cell_columns = self.width.div_ceil(cell);
+        let cell_rows = self.height.div_ceil(cell);
+        let mut output = String::new();
+        for row in (0..cell_rows).step_by(2) {
+            for column in 0..cell_columns {
+                output.push(match (printed(column, row), printed(column, row + 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                });
+            }
+            output.push('\n');
+        }
+        output
+    }
+
+    /// Writes the preview to a PNG file.
+    #[cfg(feature = "preview")]
+    pub fn write_png<P: AsRef<std::path::Path>>(&self, path: P) -> crate::printer::Result<()> {
+        if self.height == 0 {
+            bail!("Cannot render a preview without raster lines");
+        }
+        let writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+        let mut encoder = png::Encoder::new(writer, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.to_rgb())?;
+        Ok(())
+    }
+}
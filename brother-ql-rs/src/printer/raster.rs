@@ -0,0 +1,463 @@
+//! Reading CUPS and PWG raster streams
+//!
+//! Supports the compressed version 2 format, which PWG raster is based on, and the uncompressed
+//! version 3 format, in either byte order. Pages are converted to grayscale, ready to be turned into
+//! print jobs with `PrintJob::from_grayscale`.
+use std::io::Read;
+
+use crate::printer::Result;
+
+/// Size of the header in front of every page
+const PAGE_HEADER_SIZE: usize = 1796;
+/// Pages larger than this are rejected rather than decompressed
+const MAX_PAGE_PIXELS: usize = 1 << 26;
+/// Streams whose pages add up to more pixels than this are rejected, as compressed pages can be tiny
+const MAX_STREAM_PIXELS: usize = 2 * MAX_PAGE_PIXELS;
+
+// Color spaces, as defined by CUPS
+const COLOR_SPACE_W: u32 = 0;
+const COLOR_SPACE_RGB: u32 = 1;
+const COLOR_SPACE_K: u32 = 3;
+const COLOR_SPACE_SW: u32 = 18;
+const COLOR_SPACE_SRGB: u32 = 19;
+const COLOR_SPACE_ADOBE_RGB: u32 = 20;
+
+/// When the media should be cut, as requested by the `CutMedia` page device setting
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CutMedia {
+    Never,
+    AfterDocument,
+    AfterJob,
+    AfterSet,
+    AfterPage,
+}
+
+impl CutMedia {
+    fn from_value(value: u32) -> CutMedia {
+        match value {
+            1 => CutMedia::AfterDocument,
+            2 => CutMedia::AfterJob,
+            3 => CutMedia::AfterSet,
+            4 => CutMedia::AfterPage,
+            _ => CutMedia::Never,
+        }
+    }
+}
+
+pub struct RasterPage {
+    pub width: usize,
+    pub height: usize,
+    /// Horizontal and vertical resolution in dots per inch
+    pub resolution: (u32, u32),
+    /// Size of the media in points
+    pub page_size: (u32, u32),
+    /// Name of the media size, such as the `PageSize` keyword chosen in a PPD
+    pub page_size_name: String,
+    /// Number of copies requested for the page
+    pub copies: u32,
+    pub cut_media: CutMedia,
+    /// Gray value of every pixel, row by row, 0 being black
+    pub pixels: Vec<u8>,
+}
+
+struct PageHeader {
+    resolution: (u32, u32),
+    page_size: (u32, u32),
+    page_size_name: String,
+    cut_media: u32,
+    copies: u32,
+    width: usize,
+    height: usize,
+    bits_per_color: u32,
+    bits_per_pixel: u32,
+    bytes_per_line: usize,
+    color_order: u32,
+    color_space: u32,
+}
+
+impl PageHeader {
+    fn parse(header: &[u8], big_endian: bool) -> PageHeader {
+        let field = |offset: usize| {
+            let bytes = [
+                header[offset],
+                header[offset + 1],
+                header[offset + 2],
+                header[offset + 3],
+            ];
+            if big_endian {
+                u32::from_be_bytes(bytes)
+            } else {
+                u32::from_le_bytes(bytes)
+            }
+        };
+        let name = &header[1732..1796];
+        let name_length = name.iter().position(|&byte| byte == 0).unwrap_or(name.len());
+        PageHeader {
+            resolution: (field(276), field(280)),
+            page_size: (field(352), field(356)),
+            page_size_name: String::from_utf8_lossy(&name[..name_length]).into_owned(),
+            cut_media: field(268),
+            copies: field(340),
+            width: field(372) as usize,
+            height: field(376) as usize,
+            bits_per_color: field(384),
+            bits_per_pixel: field(388),
+            bytes_per_line: field(392) as usize,
+            color_order: field(396),
+            color_space: field(400),
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.width == 0 || self.height == 0 {
+            bail!("Raster page is empty");
+        }
+        if self.width.saturating_mul(self.height) > MAX_PAGE_PIXELS {
+            bail!("Raster page of {}x{} pixels is too large", self.width, self.height);
+        }
+        if self.color_order != 0 {
+            bail!("Only chunky raster data is supported");
+        }
+        let channels = match self.color_space {
+            COLOR_SPACE_W | COLOR_SPACE_K | COLOR_SPACE_SW => 1,
+            COLOR_SPACE_RGB | COLOR_SPACE_SRGB | COLOR_SPACE_ADOBE_RGB => 3,
+            other => bail!("Raster color space {} is not supported", other),
+        };
+        let supported = match (self.bits_per_color, channels) {
+            (1, 1) => self.bits_per_pixel == 1,
+            (8, channels) => self.bits_per_pixel == 8 * channels,
+            _ => false,
+        };
+        if !supported {
+            bail!(
+                "Raster data with {} bits per color and {} bits per pixel is not supported",
+                self.bits_per_color,
+                self.bits_per_pixel
+            );
+        }
+        let bytes_per_line = (self.width * self.bits_per_pixel as usize).div_ceil(8);
+        if self.bytes_per_line != bytes_per_line {
+            bail!(
+                "Raster lines of {} pixels take {} bytes, not {}",
+                self.width,
+                bytes_per_line,
+                self.bytes_per_line
+            );
+        }
+        Ok(())
+    }
+
+    /// Value of a byte of blank raster data
+    fn blank(&self) -> u8 {
+        match self.color_space {
+            COLOR_SPACE_K => 0x00,
+            _ => 0xFF,
+        }
+    }
+
+    /// Converts a raster line to gray values.
+    fn to_gray(&self, line: &[u8], gray: &mut Vec<u8>) {
+        match (self.bits_per_pixel, self.color_space) {
+            (1, color_space) => {
+                let set = if color_space == COLOR_SPACE_K { 0x00 } else { 0xFF };
+                gray.extend((0..self.width).map(|x| {
+                    if line[x / 8] & (0x80 >> (x % 8)) != 0 {
+                        set
+                    } else {
+                        !set
+                    }
+                }));
+            }
+            (8, COLOR_SPACE_K) => gray.extend(line[..self.width].iter().map(|value| !value)),
+            (8, _) => gray.extend_from_slice(&line[..self.width]),
+            _ => gray.extend(line.chunks(3).take(self.width).map(|pixel| {
+                let luma = 299 * pixel[0] as u32 + 587 * pixel[1] as u32 + 114 * pixel[2] as u32;
+                (luma / 1000) as u8
+            })),
+        }
+    }
+}
+
+/// Reads every page from a CUPS or PWG raster stream.
+pub fn read_pages<R: Read>(mut reader: R) -> Result<Vec<RasterPage>> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    let mut input = Input { data: &data, position: 0 };
+
+    let (big_endian, compressed) = match input.take(4)? {
+        b"RaS2" => (true, true),
+        b"2SaR" => (false, true),
+        b"RaS3" => (true, false),
+        b"3SaR" => (false, false),
+        _ => bail!("Not a supported CUPS or PWG raster stream"),
+    };
+
+    let mut pages = Vec::new();
+    let mut total_pixels = 0;
+    while !input.is_empty() {
+        let header = PageHeader::parse(input.take(PAGE_HEADER_SIZE)?, big_endian);
+        header.validate()?;
+        total_pixels += header.width * header.height;
+        if total_pixels > MAX_STREAM_PIXELS {
+            bail!("Raster stream has more than {} pixels", MAX_STREAM_PIXELS);
+        }
+        let mut pixels = Vec::with_capacity(header.width * header.height);
+        if compressed {
+            read_compressed(&mut input, &header, &mut pixels)?;
+        } else {
+            for _ in 0..header.height {
+                header.to_gray(input.take(header.bytes_per_line)?, &mut pixels);
+            }
+        }
+        pages.push(RasterPage {
+            width: header.width,
+            height: header.height,
+            resolution: header.resolution,
+            page_size: header.page_size,
+            page_size_name: header.page_size_name,
+            copies: header.copies.max(1),
+            cut_media: CutMedia::from_value(header.cut_media),
+            pixels,
+        });
+    }
+    Ok(pages)
+}
+
+/// Decodes the run-length encoded lines of a page.
+///
+/// Every group of identical lines starts with a repeat count, followed by runs of repeated pixels and
+/// runs of literal pixels. A run of 128 clears the rest of the line.
+fn read_compressed(input: &mut Input, header: &PageHeader, pixels: &mut Vec<u8>) -> Result<()> {
+    let pixel_size = (header.bits_per_pixel as usize / 8).max(1);
+    let mut line = Vec::with_capacity(header.bytes_per_line);
+    let mut y = 0;
+    while y < header.height {
+        let repeat = input.byte()? as usize + 1;
+        line.clear();
+        while line.len() < header.bytes_per_line {
+            match input.byte()? {
+                128 => line.resize(header.bytes_per_line, header.blank()),
+                count @ 0..=127 => {
+                    let pixel = input.take(pixel_size)?;
+                    for _ in 0..=count {
+                        line.extend_from_slice(pixel);
+                    }
+                }
+                count => line.extend_from_slice(input.take((257 - count as usize) * pixel_size)?),
+            }
+        }
+        line.truncate(header.bytes_per_line);
+        for _ in 0..repeat.min(header.height - y) {
+            header.to_gray(&line, pixels);
+        }
+        y += repeat;
+    }
+    Ok(())
+}
+
+struct Input<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Input<'a> {
+    fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8]> {
+        if self.data.len() - self.position < length {
+            bail!("Raster data ends in the middle of a page");
+        }
+        let bytes = &self.data[self.position..self.position + length];
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Header {
+        width: u32,
+        height: u32,
+        bits_per_color: u32,
+        bits_per_pixel: u32,
+        bytes_per_line: u32,
+        color_space: u32,
+    }
+
+    impl Header {
+        fn gray(width: u32, height: u32) -> Header {
+            Header {
+                width,
+                height,
+                bits_per_color: 8,
+                bits_per_pixel: 8,
+                bytes_per_line: width,
+                color_space: COLOR_SPACE_SW,
+            }
+        }
+
+        fn to_bytes(&self, big_endian: bool) -> Vec<u8> {
+            let mut header = vec![0; PAGE_HEADER_SIZE];
+            let mut field = |offset: usize, value: u32| {
+                let bytes = if big_endian {
+                    value.to_be_bytes()
+                } else {
+                    value.to_le_bytes()
+                };
+                header[offset..offset + 4].copy_from_slice(&bytes);
+            };
+            field(276, 300);
+            field(280, 300);
+            field(340, 2);
+            field(268, 4);
+            field(372, self.width);
+            field(376, self.height);
+            field(384, self.bits_per_color);
+            field(388, self.bits_per_pixel);
+            field(392, self.bytes_per_line);
+            field(400, self.color_space);
+            header[1732..1736].copy_from_slice(b"62mm");
+            header
+        }
+    }
+
+    fn stream(magic: &[u8; 4], header: &Header, big_endian: bool, data: &[u8]) -> Vec<u8> {
+        let mut stream = magic.to_vec();
+        stream.extend(header.to_bytes(big_endian));
+        stream.extend_from_slice(data);
+        stream
+    }
+
+    #[test]
+    fn uncompressed_in_both_byte_orders() {
+        let pixels = [0, 64, 128, 255, 10, 20];
+        for (magic, big_endian) in [(b"RaS3", true), (b"3SaR", false)] {
+            let data = stream(magic, &Header::gray(3, 2), big_endian, &pixels);
+            let pages = read_pages(&data[..]).unwrap();
+            assert_eq!(pages.len(), 1);
+            let page = &pages[0];
+            assert_eq!((page.width, page.height), (3, 2));
+            assert_eq!(page.resolution, (300, 300));
+            assert_eq!(page.page_size_name, "62mm");
+            assert_eq!(page.copies, 2);
+            assert_eq!(page.cut_media, CutMedia::AfterPage);
+            assert_eq!(page.pixels, pixels);
+        }
+    }
+
+    #[test]
+    fn compressed_in_both_byte_orders() {
+        let data = [
+            // Two identical lines: 3 pixels of 0x10, then the literal pixels 0x20 0x30
+            1, 2, 0x10, 0xFF, 0x20, 0x30,
+            // One line of a pixel of 0x40, the rest cleared to white
+            0, 0, 0x40, 128,
+        ];
+        for (magic, big_endian) in [(b"RaS2", true), (b"2SaR", false)] {
+            let raster = stream(magic, &Header::gray(5, 3), big_endian, &data);
+            let pages = read_pages(&raster[..]).unwrap();
+            assert_eq!(
+                pages[0].pixels,
+                [
+                    0x10, 0x10, 0x10, 0x20, 0x30, 0x10, 0x10, 0x10, 0x20, 0x30, 0x40, 0xFF, 0xFF,
+                    0xFF, 0xFF
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn multiple_pages() {
+        let mut data = stream(b"3SaR", &Header::gray(1, 1), false, &[7]);
+        data.extend(Header::gray(2, 1).to_bytes(false));
+        data.extend([8, 9]);
+        let pages = read_pages(&data[..]).unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[1].pixels, [8, 9]);
+    }
+
+    #[test]
+    fn bitmap_and_color_pages() {
+        let header = Header {
+            width: 10,
+            height: 1,
+            bits_per_color: 1,
+            bits_per_pixel: 1,
+            bytes_per_line: 2,
+            color_space: COLOR_SPACE_K,
+        };
+        let data = stream(b"3SaR", &header, false, &[0b1000_0001, 0b0100_0000]);
+        let pages = read_pages(&data[..]).unwrap();
+        assert_eq!(pages[0].pixels, [0, 255, 255, 255, 255, 255, 255, 0, 255, 0]);
+
+        let header = Header {
+            width: 2,
+            height: 1,
+            bits_per_color: 8,
+            bits_per_pixel: 24,
+            bytes_per_line: 6,
+            color_space: COLOR_SPACE_SRGB,
+        };
+        let data = stream(b"3SaR", &header, false, &[255, 255, 255, 255, 0, 0]);
+        let pages = read_pages(&data[..]).unwrap();
+        assert_eq!(pages[0].pixels, [255, 76]);
+    }
+
+    #[test]
+    fn rejects_invalid_streams() {
+        assert!(read_pages(&b"RaS1"[..]).is_err());
+        let truncated = stream(b"3SaR", &Header::gray(3, 2), false, &[0, 0, 0]);
+        assert!(read_pages(&truncated[..]).is_err());
+        let truncated = stream(b"2SaR", &Header::gray(3, 2), false, &[0, 0xFE, 1]);
+        assert!(read_pages(&truncated[..]).is_err());
+        let header = b"3SaR".iter().chain(&[0; 100]).copied().collect::<Vec<_>>();
+        assert!(read_pages(&header[..]).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        let invalid = |header: Header| {
+            let data = stream(b"2SaR", &header, false, &[0, 128]);
+            read_pages(&data[..]).is_err()
+        };
+        assert!(invalid(Header::gray(0, 1)));
+        assert!(invalid(Header::gray(1, 0)));
+        assert!(invalid(Header::gray(1 << 14, 1 << 14)));
+        assert!(invalid(Header {
+            bytes_per_line: u32::MAX,
+            ..Header::gray(3, 1)
+        }));
+        assert!(invalid(Header {
+            bytes_per_line: 2,
+            ..Header::gray(3, 1)
+        }));
+        assert!(invalid(Header {
+            bits_per_pixel: 16,
+            ..Header::gray(3, 1)
+        }));
+        assert!(invalid(Header {
+            color_space: 6,
+            ..Header::gray(3, 1)
+        }));
+    }
+
+    #[test]
+    fn rejects_streams_with_too_many_pixels() {
+        // Every group of 256 lines clears them to white
+        let header = Header::gray(1 << 13, 1 << 13);
+        let mut data = b"RaS2".to_vec();
+        for _ in 0..3 {
+            data.extend(header.to_bytes(true));
+            data.extend([255, 128].repeat(1 << 5));
+        }
+        assert!(read_pages(&data[..]).is_err());
+    }
+}
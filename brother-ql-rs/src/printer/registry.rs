@@ -0,0 +1,159 @@
+//! User-defined label media
+//!
+//! Labels registered here are returned by `constants::label_data` in place of built-in labels of
+//! the same size, so third-party rolls and sizes the built-in table doesn't know can be printed on.
+//!
+//! Labels can also be loaded from a file with one label per line, listing the fields of `Label`
+//! separated by whitespace. Empty lines and lines starting with `#` are ignored:
+//!
+//! ```text
+//! # width length dots_w dots_l printable_w printable_l right_margin feed_margin
+//! 62      29     732    341    696         271         12           0
+//! 58      0      685    0      649         0           43           35
+//! ```
+use std::fs;
+use std::path::Path;
+use std::sync::RwLock;
+
+use crate::printer::constants::{Label, WidthLength, MAX_PIXEL_WIDTH};
+use crate::printer::{Result, ResultExt};
+
+static CUSTOM_LABELS: RwLock<Vec<Label>> = RwLock::new(Vec::new());
+
+/// Registers a label, replacing any previously registered label with the same tape size.
+///
+/// Use a tape length of `0` for continuous tape. Fails if the label has no width or its printable
+/// area doesn't fit the print head.
+pub fn register_label(label: Label) -> Result<()> {
+    validate(&label)?;
+    let mut labels = CUSTOM_LABELS.write().unwrap();
+    labels.retain(|existing| !same_size(existing, label.tape_size.0, label.tape_size.1));
+    labels.push(label);
+    Ok(())
+}
+
+/// Removes a registered label, returning it if there was one.
+pub fn unregister_label(width: u32, length: u32) -> Option<Label> {
+    let mut labels = CUSTOM_LABELS.write().unwrap();
+    let index = labels
+        .iter()
+        .position(|label| same_size(label, width, length))?;
+    Some(labels.remove(index))
+}
+
+/// All currently registered labels
+pub fn custom_labels() -> Vec<Label> {
+    CUSTOM_LABELS.read().unwrap().clone()
+}
+
+/// Loads labels from a file and registers them, returning how many were registered.
+pub fn load_labels<P: AsRef<Path>>(path: P) -> Result<usize> {
+    let labels = parse_labels(&fs::read_to_string(path)?)?;
+    let count = labels.len();
+    for label in labels {
+        register_label(label)?;
+    }
+    Ok(count)
+}
+
+/// Parses labels in the file format described in the module documentation.
+pub fn parse_labels(definitions: &str) -> Result<Vec<Label>> {
+    let mut labels = Vec::new();
+    for (index, line) in definitions.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let values = line
+            .split_whitespace()
+            .map(|value| value.parse::<u32>())
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .chain_err(|| format!("Line {}: label values must be numbers", index + 1))?;
+        if values.len() != 8 {
+            bail!("Line {}: expected 8 values, found {}", index + 1, values.len());
+        }
+        if values[6] > u8::MAX as u32 || values[7] > u8::MAX as u32 {
+            bail!("Line {}: margins must be at most {} dots", index + 1, u8::MAX);
+        }
+        let label = Label {
+            tape_size: WidthLength(values[0], values[1]),
+            dots: WidthLength(values[2], values[3]),
+            dots_printable: WidthLength(values[4], values[5]),
+            right_margin: values[6] as u8,
+            feed_margin: values[7] as u8,
+        };
+        validate(&label).chain_err(|| format!("Line {}: invalid label", index + 1))?;
+        labels.push(label);
+    }
+    Ok(labels)
+}
+
+/// Checks that a label can be printed on: it must have a width, and its printable area must end
+/// within the `MAX_PIXEL_WIDTH` dots of the print head.
+fn validate(label: &Label) -> Result<()> {
+    if label.tape_size.0 == 0 || label.dots.0 == 0 || label.dots_printable.0 == 0 {
+        bail!("Labels must have a width");
+    }
+    let end = label.right_margin as u64 + label.dots_printable.0 as u64;
+    if end > MAX_PIXEL_WIDTH as u64 {
+        bail!(
+            "The printable area ends {} dots from the edge, past the {} dots of the print head",
+            end,
+            MAX_PIXEL_WIDTH
+        );
+    }
+    Ok(())
+}
+
+pub(crate) fn lookup(width: u8, length: Option<u8>) -> Option<Label> {
+    let length = length.unwrap_or(0) as u32;
+    CUSTOM_LABELS
+        .read()
+        .unwrap()
+        .iter()
+        .find(|label| same_size(label, width as u32, length))
+        .copied()
+}
+
+fn same_size(label: &Label, width: u32, length: u32) -> bool {
+    label.tape_size.0 == width && label.tape_size.1 == length
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_labels() {
+        let definitions = "# comment\n\n62 29 732 341 696 271 12 0\n58 0 685 0 649 0 43 35\n";
+        let labels = parse_labels(definitions).unwrap();
+        assert_eq!(labels.len(), 2);
+        assert_eq!(labels[1].tape_size, WidthLength(58, 0));
+        assert_eq!(labels[1].printable_dots(), 43..692);
+    }
+
+    #[test]
+    fn rejects_invalid_labels() {
+        assert!(parse_labels("62 29 732 341 696").is_err());
+        assert!(parse_labels("62 29 732 341 696 271 256 0").is_err());
+        // No width
+        assert!(parse_labels("0 29 732 341 696 271 12 0").is_err());
+        assert!(parse_labels("62 29 732 341 0 271 12 0").is_err());
+        // Ends past the print head
+        assert!(parse_labels("62 29 732 341 709 271 12 0").is_err());
+        assert!(parse_labels("62 29 732 341 708 271 12 0").is_ok());
+    }
+
+    #[test]
+    fn does_not_register_invalid_labels() {
+        let label = Label {
+            tape_size: WidthLength(61, 0),
+            dots: WidthLength(732, 0),
+            dots_printable: WidthLength(u32::MAX, 0),
+            right_margin: 12,
+            feed_margin: 35,
+        };
+        assert!(register_label(label).is_err());
+        assert!(lookup(61, None).is_none());
+    }
+}
@@ -1,13 +1,17 @@
+use crate::printer::constants::RASTER_LINE_LENGTH;
 
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum PrinterSetting {
     SwitchToRasterMode,
     MirrorOrCut(bool, bool),
+    CutEvery(u8),
     HighResMode(bool),
     NormalResMode(bool),
     PowerOnWhenConnected(bool),
     SleepTimer(SleepTimerValue),
 }
 
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum SleepTimerValue {
     Disable,
     TurnOffAfter10Minutes,
@@ -18,32 +22,95 @@ pub enum SleepTimerValue {
     TurnOffAfter60Minutes,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Resolution {
-    /// 600 dpi. Has a 2:1 height-to-with proportion.
+    /// 300 x 300 dpi. Dots are square.
     Normal,
-    /// 300 dpi. Has a 1:1 height-to-with proportion.
+    /// 300 dpi across the print head, 600 dpi along the tape. Raster lines are half as tall as
+    /// they are wide, so twice as many lines are needed for the same label length.
+    ///
+    /// Not supported by the QL-500, QL-550, QL-560 and QL-650TD.
     High,
 }
 
+impl Resolution {
+    /// Horizontal and vertical resolution in dots per inch
+    pub fn dpi(&self) -> (u32, u32) {
+        match self {
+            Resolution::Normal => (300, 300),
+            Resolution::High => (300, 600),
+        }
+    }
+
+    /// Number of raster lines that make up one square pixel
+    pub fn lines_per_pixel(&self) -> usize {
+        match self {
+            Resolution::Normal => 1,
+            Resolution::High => 2,
+        }
+    }
+
+    /// Converts raster lines of an image with square pixels, repeating every line as often as
+    /// needed to keep the image's aspect ratio at this resolution.
+    pub fn scale_lines(&self, lines: &[[u8; RASTER_LINE_LENGTH]]) -> Vec<[u8; RASTER_LINE_LENGTH]> {
+        lines
+            .iter()
+            .flat_map(|&line| std::iter::repeat_n(line, self.lines_per_pixel()))
+            .collect()
+    }
+}
+
+/// Persistent device settings, as far as they are known from the settings applied, see
+/// `ThermalPrinter::device_settings`
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DeviceSettings {
+    pub auto_power_on: Option<bool>,
+    pub auto_power_off: Option<SleepTimerValue>,
+}
+
+impl DeviceSettings {
+    pub(crate) fn record(&mut self, setting: &PrinterSetting) {
+        match setting {
+            PrinterSetting::PowerOnWhenConnected(on) => self.auto_power_on = Some(*on),
+            PrinterSetting::SleepTimer(value) => self.auto_power_off = Some(*value),
+            _ => {}
+        }
+    }
+}
+
 impl PrinterSetting {
-    pub fn get_byte_sequence(&self) -> [u8; 4] {
+    /// Whether the setting is stored by the printer, rather than only applying to the current job
+    pub fn is_device_setting(&self) -> bool {
+        matches!(
+            self,
+            PrinterSetting::PowerOnWhenConnected(_) | PrinterSetting::SleepTimer(_)
+        )
+    }
+
+    /// The command applying the setting.
+    ///
+    /// Returns a `Vec<u8>` rather than the `[u8; 4]` of earlier versions, as the device settings
+    /// take 6 bytes.
+    pub fn get_byte_sequence(&self) -> Vec<u8> {
         match self {
-            PrinterSetting::SwitchToRasterMode => [0x1B, 0x69, 0x61, 0x01],
+            PrinterSetting::SwitchToRasterMode => vec![0x1B, 0x69, 0x61, 0x01],
             PrinterSetting::MirrorOrCut(mirror, auto_cut) => {
                 let mirror_bit = if *mirror { 1 } else { 0 };
                 let cut_bit = if *auto_cut { 1 } else { 0 };
-                [0x1B, 0x69, 0x4d, mirror_bit << 7 | cut_bit << 6]
+                vec![0x1B, 0x69, 0x4d, mirror_bit << 7 | cut_bit << 6]
             },
+            PrinterSetting::CutEvery(pages) => vec![0x1B, 0x69, 0x41, *pages],
             PrinterSetting::NormalResMode(cut) => {
                 let cut_bit = if *cut { 1 } else { 0 };
-                [0x1B, 0x69, 0x4B, cut_bit << 3]
+                vec![0x1B, 0x69, 0x4B, cut_bit << 3]
             }
             PrinterSetting::HighResMode(cut) => {
                 let cut_bit = if *cut { 1 } else { 0 };
-                [0x1B, 0x69, 0x4b, cut_bit << 3 | 1 << 6]
+                vec![0x1B, 0x69, 0x4b, cut_bit << 3 | 1 << 6]
             }
+            // Device settings are all `ESC i U`, followed by the setting and its value
             PrinterSetting::PowerOnWhenConnected(on) => {
-                [0x1B, 0x69, 0x70, if *on { 0x00 } else { 0x01 }]
+                vec![0x1B, 0x69, 0x55, 0x70, 0x00, if *on { 0x00 } else { 0x01 }]
             }
             PrinterSetting::SleepTimer(value) => {
                 let last_byte = match value {
@@ -55,8 +122,90 @@ impl PrinterSetting {
                     SleepTimerValue::TurnOffAfter50Minutes => 0x05,
                     SleepTimerValue::TurnOffAfter60Minutes => 0x06,
                 };
-                [0x1B, 0x69, 0x70, last_byte]
+                vec![0x1B, 0x69, 0x55, 0x41, 0x00, last_byte]
             }
         }
     }
-}
\ No newline at end of file
+}
+
+/// The expected bytes are those of the commands in the Brother QL series command reference linked
+/// from the `printer` module. Device settings (`ESC i U`) have no variants for other models, as of
+/// the supported models only the QL-700's reference lists them, see
+/// `PrinterModel::supports_power_settings`.
+#[cfg(test)]
+mod tests {
+    use super::PrinterSetting::*;
+    use super::SleepTimerValue::*;
+
+    #[test]
+    fn switch_to_raster_mode() {
+        // ESC i a {n}, switch dynamic command mode, n = 01h: raster mode
+        assert_eq!(SwitchToRasterMode.get_byte_sequence(), [0x1B, 0x69, 0x61, 0x01]);
+    }
+
+    #[test]
+    fn mirror_or_cut() {
+        // ESC i M {n}, various mode settings: bit 6 auto cut, bit 7 mirror printing
+        assert_eq!(MirrorOrCut(false, false).get_byte_sequence(), [0x1B, 0x69, 0x4D, 0x00]);
+        assert_eq!(MirrorOrCut(false, true).get_byte_sequence(), [0x1B, 0x69, 0x4D, 0x40]);
+        assert_eq!(MirrorOrCut(true, false).get_byte_sequence(), [0x1B, 0x69, 0x4D, 0x80]);
+        assert_eq!(MirrorOrCut(true, true).get_byte_sequence(), [0x1B, 0x69, 0x4D, 0xC0]);
+    }
+
+    #[test]
+    fn cut_every() {
+        // ESC i A {n}, cut every n labels, 1 to 255
+        assert_eq!(CutEvery(1).get_byte_sequence(), [0x1B, 0x69, 0x41, 0x01]);
+        assert_eq!(CutEvery(255).get_byte_sequence(), [0x1B, 0x69, 0x41, 0xFF]);
+    }
+
+    #[test]
+    fn resolution_modes() {
+        // ESC i K {n}, expanded mode: bit 3 cut at end, bit 6 high resolution printing
+        assert_eq!(NormalResMode(false).get_byte_sequence(), [0x1B, 0x69, 0x4B, 0x00]);
+        assert_eq!(NormalResMode(true).get_byte_sequence(), [0x1B, 0x69, 0x4B, 0x08]);
+        assert_eq!(HighResMode(false).get_byte_sequence(), [0x1B, 0x69, 0x4B, 0x40]);
+        assert_eq!(HighResMode(true).get_byte_sequence(), [0x1B, 0x69, 0x4B, 0x48]);
+    }
+
+    #[test]
+    fn power_on_when_connected() {
+        // ESC i U p 00h {n}, auto power on. The value of n for on and off is the one this crate always
+        // sent, 00h for on, which couldn't be checked against a printer yet
+        assert_eq!(
+            PowerOnWhenConnected(true).get_byte_sequence(),
+            [0x1B, 0x69, 0x55, 0x70, 0x00, 0x00]
+        );
+        assert_eq!(
+            PowerOnWhenConnected(false).get_byte_sequence(),
+            [0x1B, 0x69, 0x55, 0x70, 0x00, 0x01]
+        );
+    }
+
+    #[test]
+    fn sleep_timer() {
+        // ESC i U A 00h {n}, auto power off after n times 10 minutes, 00h: never
+        let values = [
+            (Disable, 0x00),
+            (TurnOffAfter10Minutes, 0x01),
+            (TurnOffAfter20Minutes, 0x02),
+            (TurnOffAfter30Minutes, 0x03),
+            (TurnOffAfter40Minutes, 0x04),
+            (TurnOffAfter50Minutes, 0x05),
+            (TurnOffAfter60Minutes, 0x06),
+        ];
+        for (value, byte) in values.iter() {
+            assert_eq!(
+                SleepTimer(*value).get_byte_sequence(),
+                [0x1B, 0x69, 0x55, 0x41, 0x00, *byte]
+            );
+        }
+    }
+
+    #[test]
+    fn device_settings_use_distinct_commands() {
+        let power_on = PowerOnWhenConnected(true).get_byte_sequence();
+        let sleep_timer = SleepTimer(Disable).get_byte_sequence();
+        assert_ne!(power_on[..5], sleep_timer[..5]);
+    }
+}
@@ -0,0 +1,305 @@
+//! Printing from several threads through one printer
+//!
+//! A `Spooler` takes ownership of a printer and prints the jobs submitted to it one after another on a
+//! background thread, so that the commands of concurrent jobs never interleave.
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+use log::{debug, warn};
+
+use crate::printer::config::PrinterConfig;
+use crate::printer::job::PrintJob;
+use crate::printer::{status, Error, ErrorKind, Result, ThermalPrinter};
+
+/// Errors reported by the printer that can be fixed by someone attending to it
+const RECOVERABLE_ERRORS: [&str; 6] = [
+    "No media when printing",
+    "End of media",
+    "Tape cutter jam",
+    "Main unit in use",
+    "Transmission error",
+    "Cover open",
+];
+
+#[derive(Debug, Clone)]
+pub struct SpoolerConfig {
+    /// How often a job is tried before it fails, including the first attempt
+    pub attempts: u32,
+    /// How long to wait for the printer to become ready again after a recoverable error
+    pub recovery_timeout: Duration,
+    /// How often the printer is asked for its status while waiting for it to recover
+    pub recovery_poll_interval: Duration,
+}
+
+impl Default for SpoolerConfig {
+    fn default() -> Self {
+        SpoolerConfig {
+            attempts: 3,
+            recovery_timeout: Duration::from_secs(10 * 60),
+            recovery_poll_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobState {
+    /// Waiting for earlier jobs to finish
+    Queued,
+    /// Being sent to the printer or printed, counting attempts from 1
+    Printing { attempt: u32 },
+    /// The printer reported these errors, the job is retried once they are fixed
+    WaitingForRecovery(Vec<&'static str>),
+    Completed,
+    Failed(String),
+}
+
+impl JobState {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, JobState::Completed | JobState::Failed(_))
+    }
+}
+
+/// A job submitted to a `Spooler`
+#[derive(Debug)]
+pub struct JobHandle {
+    id: u64,
+    state: Arc<Mutex<JobState>>,
+    result: Receiver<Result<status::Response>>,
+}
+
+impl JobHandle {
+    /// Identifies the job among the jobs submitted to the same spooler, starting at 1
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn state(&self) -> JobState {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Blocks until the job is finished and returns the status the printer reported for its last page.
+    pub fn wait(self) -> Result<status::Response> {
+        match self.result.recv() {
+            Ok(result) => result,
+            Err(_) => bail!("The spooler stopped before job {} was printed", self.id),
+        }
+    }
+}
+
+struct Request {
+    id: u64,
+    jobs: Vec<PrintJob>,
+    state: Arc<Mutex<JobState>>,
+    result: Sender<Result<status::Response>>,
+}
+
+impl Request {
+    fn set_state(&self, state: JobState) {
+        *self.state.lock().unwrap() = state;
+    }
+}
+
+enum Message {
+    Print(Request),
+    Status(Sender<Result<status::Response>>),
+}
+
+/// Owns a printer and prints submitted jobs in order on a background thread.
+///
+/// Every job is awaited until the printer reports it as printed before the next one is started. Jobs
+/// failing because of a recoverable printer error, such as an open cover or running out of labels, are
+/// retried once the printer reports that the error was fixed, starting at the first page that wasn't
+/// printed yet.
+///
+/// Dropping the spooler blocks until the queued jobs are finished.
+pub struct Spooler {
+    requests: Option<Sender<Message>>,
+    next_id: AtomicU64,
+    /// Jobs queued or being printed
+    pending: Arc<AtomicUsize>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Spooler {
+    pub fn new<T: rusb::UsbContext + 'static>(printer: ThermalPrinter<T>) -> Result<Spooler> {
+        Spooler::with_config(printer, SpoolerConfig::default())
+    }
+
+    pub fn with_config<T: rusb::UsbContext + 'static>(
+        printer: ThermalPrinter<T>,
+        config: SpoolerConfig,
+    ) -> Result<Spooler> {
+        let (requests, receiver) = mpsc::channel();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let worker_pending = pending.clone();
+        let thread = thread::Builder::new()
+            .name("brother-ql-spooler".to_string())
+            .spawn(move || run(printer, config, receiver, &worker_pending))?;
+        Ok(Spooler {
+            requests: Some(requests),
+            next_id: AtomicU64::new(1),
+            pending,
+            thread: Some(thread),
+        })
+    }
+
+    /// Queues a single page, see `ThermalPrinter::print`.
+    pub fn submit(&self, job: PrintJob) -> JobHandle {
+        self.submit_pages(vec![job])
+    }
+
+    /// Queues several pages to be printed as one job, see `ThermalPrinter::print_pages`.
+    pub fn submit_pages(&self, jobs: Vec<PrintJob>) -> JobHandle {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let state = Arc::new(Mutex::new(JobState::Queued));
+        let (sender, result) = mpsc::channel();
+        let request = Request {
+            id,
+            jobs,
+            state: state.clone(),
+            result: sender,
+        };
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        let sent = match &self.requests {
+            Some(requests) => requests.send(Message::Print(request)).is_ok(),
+            None => false,
+        };
+        if !sent {
+            // The request was dropped along with the worker, which `JobHandle::wait` reports
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+        }
+        JobHandle { id, state, result }
+    }
+
+    /// Asks the printer for its status once the jobs submitted before are finished.
+    pub fn status(&self) -> Result<status::Response> {
+        let (sender, result) = mpsc::channel();
+        if let Some(requests) = &self.requests {
+            let _ = requests.send(Message::Status(sender));
+        }
+        match result.recv() {
+            Ok(result) => result,
+            Err(_) => bail!("The spooler stopped"),
+        }
+    }
+
+    /// Whether no job is queued or being printed
+    pub fn is_idle(&self) -> bool {
+        self.pending.load(Ordering::SeqCst) == 0
+    }
+}
+
+impl Drop for Spooler {
+    fn drop(&mut self) {
+        self.requests.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run<T: rusb::UsbContext>(
+    printer: ThermalPrinter<T>,
+    config: SpoolerConfig,
+    messages: Receiver<Message>,
+    pending: &AtomicUsize,
+) {
+    for message in messages {
+        let request = match message {
+            Message::Print(request) => request,
+            Message::Status(result) => {
+                let _ = result.send(printer.get_status());
+                continue;
+            }
+        };
+        debug!("Printing job {}", request.id);
+        let started = SystemTime::now();
+        let mut printed = 0;
+        let result = print(&printer, &config, &request, &mut printed);
+        printer.record(&request.jobs, printed, started, true, &result);
+        match &result {
+            Ok(_) => request.set_state(JobState::Completed),
+            Err(error) => {
+                warn!("Job {} failed: {}", request.id, error);
+                request.set_state(JobState::Failed(error.to_string()));
+            }
+        }
+        pending.fetch_sub(1, Ordering::SeqCst);
+        let _ = request.result.send(result);
+    }
+}
+
+fn print<T: rusb::UsbContext>(
+    printer: &ThermalPrinter<T>,
+    config: &SpoolerConfig,
+    request: &Request,
+    printed: &mut usize,
+) -> Result<status::Response> {
+    let mut attempt = 1;
+    loop {
+        request.set_state(JobState::Printing { attempt });
+        // Pages printed by earlier attempts aren't printed again
+        let jobs = &request.jobs[*printed..];
+        let result = printer
+            .send_pages(jobs)
+            .and_then(status::Response::into_result)
+            .and_then(|_| printer.wait_for_pages(jobs.len(), printed, |_| Ok(())));
+        let error = match result {
+            Ok(status) => return Ok(status),
+            Err(error) => error,
+        };
+        if attempt >= config.attempts || !is_recoverable(&error) {
+            return Err(error);
+        }
+
+        warn!(
+            "Job {} failed after {} of {} pages: {}, retrying once the printer is ready ({}/{})",
+            request.id,
+            printed,
+            request.jobs.len(),
+            error,
+            attempt,
+            config.attempts
+        );
+        if let ErrorKind::Printer(errors) = error.kind() {
+            request.set_state(JobState::WaitingForRecovery(errors.clone()));
+        }
+        // Discard whatever part of the job the printer already received
+        printer.invalidate()?;
+        printer.initialize()?;
+        wait_until_ready(printer, config)?;
+        attempt += 1;
+    }
+}
+
+fn is_recoverable(error: &Error) -> bool {
+    match error.kind() {
+        ErrorKind::Printer(errors) => errors.iter().all(|error| RECOVERABLE_ERRORS.contains(error)),
+        ErrorKind::USB(error) => PrinterConfig::is_transient(error),
+        _ => false,
+    }
+}
+
+fn wait_until_ready<T: rusb::UsbContext>(
+    printer: &ThermalPrinter<T>,
+    config: &SpoolerConfig,
+) -> Result<()> {
+    let deadline = Instant::now() + config.recovery_timeout;
+    loop {
+        match printer.get_status() {
+            Ok(status) if status.errors.is_empty() => return Ok(()),
+            Err(Error(ErrorKind::Disconnected, _)) => bail!(ErrorKind::Disconnected),
+            _ => {}
+        }
+        if Instant::now() >= deadline {
+            bail!(
+                "The printer didn't become ready within {:?}",
+                config.recovery_timeout
+            );
+        }
+        thread::sleep(config.recovery_poll_interval);
+    }
+}
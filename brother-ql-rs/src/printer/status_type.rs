@@ -1,5 +1,6 @@
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum StatusType {
     ReplyToStatusRequest,
     PrintingCompleted,
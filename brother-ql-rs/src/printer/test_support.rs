@@ -0,0 +1,20 @@
+//! Fixtures shared by the unit tests of the printer modules
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::printer::catalogue;
+use crate::printer::constants::Label;
+
+/// The label of a catalogue entry, such as "62" or "29x90"
+pub fn label(name: &str) -> Label {
+    catalogue::find(name).unwrap().label
+}
+
+/// A file in the temporary directory of its own for every test and process, removed beforehand
+pub fn temp_path(prefix: &str, name: &str) -> PathBuf {
+    let name = format!("brother-ql-{}-{}-{}", prefix, std::process::id(), name);
+    let path = std::env::temp_dir().join(name);
+    let _ = fs::remove_file(&path);
+    path
+}
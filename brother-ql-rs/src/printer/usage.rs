@@ -0,0 +1,304 @@
+//! Estimating how much of the loaded roll is left
+//!
+//! Printers don't report how much tape is left on a roll, so a `UsageTracker` adds up what every
+//! printer used since its roll was last replaced: the length of continuous tape, from the raster lines
+//! and feed margins of each page, or the number of die-cut labels. Together with the contents of a full
+//! roll, listed in the `catalogue`, this gives an estimate of what is left.
+//!
+//! Attach a tracker to a printer with `ThermalPrinter::set_usage_tracker`. Usage is kept in a JSON file
+//! so it survives restarts. Loading media of a different size is taken as the roll being replaced,
+//! otherwise call `UsageTracker::roll_replaced` when a roll is replaced. A warning is logged whenever
+//! the part of a roll that is left drops below one of the thresholds.
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::printer::catalogue::{self, Roll};
+use crate::printer::constants::{self, Label};
+use crate::printer::job::PrintJob;
+use crate::printer::{Result, ResultExt};
+
+/// Parts of a roll left at which a warning is logged by default
+pub const DEFAULT_THRESHOLDS: [f64; 2] = [0.2, 0.05];
+
+/// Usage of the roll loaded into a printer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RollUsage {
+    /// Tape size of the roll in mm, with a length of `0` for continuous tape
+    pub width: u32,
+    pub length: u32,
+    /// What the full roll held, if known
+    pub capacity: Option<Roll>,
+    /// Continuous tape used, in mm
+    pub millimeters_used: f64,
+    /// Die-cut labels used
+    pub labels_used: u64,
+    /// When the roll was replaced, in seconds since the Unix epoch
+    pub replaced: u64,
+}
+
+impl RollUsage {
+    fn new(label: &Label) -> RollUsage {
+        let (width, length) = (label.tape_size.0, label.tape_size.1);
+        RollUsage {
+            width,
+            length,
+            capacity: catalogue::find_by_size(width, length).and_then(|entry| entry.roll),
+            millimeters_used: 0.0,
+            labels_used: 0,
+            replaced: now(),
+        }
+    }
+
+    /// Part of the roll that is left, from `0.0` to `1.0`, if the capacity of the roll is known
+    pub fn remaining(&self) -> Option<f64> {
+        let used = match self.capacity? {
+            Roll::Millimeters(capacity) => self.millimeters_used / capacity as f64,
+            Roll::Labels(capacity) => self.labels_used as f64 / capacity as f64,
+        };
+        Some((1.0 - used).max(0.0))
+    }
+
+    /// The label the roll holds, if it is a known one
+    pub fn label(&self) -> Option<Label> {
+        let length = if self.length == 0 {
+            None
+        } else {
+            Some(self.length as u8)
+        };
+        constants::label_data(self.width as u8, length)
+    }
+
+    fn is_roll(&self, label: &Label) -> bool {
+        self.width == label.tape_size.0 && self.length == label.tape_size.1
+    }
+}
+
+/// Usage of the rolls of all printers, by serial number, safe to share between printers
+pub struct UsageTracker {
+    path: PathBuf,
+    thresholds: Vec<f64>,
+    rolls: Mutex<BTreeMap<String, RollUsage>>,
+}
+
+impl UsageTracker {
+    /// Opens the usage file at `path`, which is created when usage is first recorded.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<UsageTracker> {
+        let path = path.as_ref().to_path_buf();
+        let rolls = if path.exists() {
+            let data = fs::read(&path)?;
+            serde_json::from_slice(&data)
+                .chain_err(|| format!("Usage file {} is invalid", path.display()))?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(UsageTracker {
+            path,
+            thresholds: DEFAULT_THRESHOLDS.to_vec(),
+            rolls: Mutex::new(rolls),
+        })
+    }
+
+    /// Sets the parts of a roll left, from `0.0` to `1.0`, at which a warning is logged.
+    pub fn set_thresholds(&mut self, thresholds: &[f64]) {
+        self.thresholds = thresholds.to_vec();
+    }
+
+    /// Adds the pages of a job printed on `label` to the usage of a printer, returning its new usage.
+    pub fn record(&self, serial_number: &str, label: &Label, jobs: &[PrintJob]) -> Result<RollUsage> {
+        let mut rolls = self.rolls.lock().unwrap();
+        let usage = rolls
+            .entry(serial_number.to_string())
+            .or_insert_with(|| RollUsage::new(label));
+        if !usage.is_roll(label) {
+            info!(
+                "Printer {} has a new roll of {}x{}mm labels",
+                serial_number, label.tape_size.0, label.tape_size.1
+            );
+            *usage = RollUsage::new(label);
+        }
+
+        let remaining = usage.remaining();
+        for job in jobs {
+            if label.is_die_cut() {
+                usage.labels_used += 1;
+            } else {
+                usage.millimeters_used += page_length(label, job);
+            }
+        }
+        if let (Some(before), Some(after)) = (remaining, usage.remaining()) {
+            if self.crosses_threshold(before, after) {
+                warn!(
+                    "The roll of printer {} is running low, about {:.0}% is left",
+                    serial_number,
+                    after * 100.0
+                );
+            }
+        }
+
+        let usage = usage.clone();
+        self.save(&rolls)?;
+        Ok(usage)
+    }
+
+    /// Whether the part of a roll that is left dropped below one of the thresholds
+    fn crosses_threshold(&self, before: f64, after: f64) -> bool {
+        self.thresholds
+            .iter()
+            .any(|&threshold| after < threshold && before >= threshold)
+    }
+
+    /// Usage of the roll of a printer, if anything was printed on it
+    pub fn usage(&self, serial_number: &str) -> Option<RollUsage> {
+        self.rolls.lock().unwrap().get(serial_number).cloned()
+    }
+
+    /// Usage of the rolls of all printers, by serial number
+    pub fn all(&self) -> BTreeMap<String, RollUsage> {
+        self.rolls.lock().unwrap().clone()
+    }
+
+    /// Resets the usage of a printer after its roll was replaced with one of the same size.
+    ///
+    /// `capacity` sets what the new roll holds, for rolls not in the catalogue or of a different
+    /// length. Otherwise the capacity of the previous roll is kept.
+    pub fn roll_replaced(&self, serial_number: &str, capacity: Option<Roll>) -> Result<RollUsage> {
+        let mut rolls = self.rolls.lock().unwrap();
+        let usage = match rolls.get_mut(serial_number) {
+            Some(usage) => usage,
+            None => bail!("Nothing was printed on printer {} yet", serial_number),
+        };
+        usage.millimeters_used = 0.0;
+        usage.labels_used = 0;
+        usage.replaced = now();
+        if capacity.is_some() {
+            usage.capacity = capacity;
+        }
+        let usage = usage.clone();
+        self.save(&rolls)?;
+        Ok(usage)
+    }
+
+    /// Writes the usage file, replacing it only once it was written completely.
+    fn save(&self, rolls: &BTreeMap<String, RollUsage>) -> Result<()> {
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        fs::write(&temporary, serde_json::to_vec_pretty(rolls)?)
+            .chain_err(|| format!("Can't write usage file {}", self.path.display()))?;
+        fs::rename(&temporary, &self.path)?;
+        Ok(())
+    }
+}
+
+/// Length of tape used by a page of continuous tape in mm, including the feed margins before and
+/// after it
+fn page_length(label: &Label, job: &PrintJob) -> f64 {
+    let lines_per_mm = job.resolution.dpi().1 as f64 / 25.4;
+    let feed_margin = job.feed_margin.map_or(label.feed_margin as u16, |margin| margin.to_dots());
+    // Feed margins are in dots of the print head, which has 300 dots per inch
+    job.raster_lines.len() as f64 / lines_per_mm + 2.0 * feed_margin as f64 * 25.4 / 300.0
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::job::Margin;
+    use crate::printer::setting::Resolution;
+    use crate::printer::test_support::{label, temp_path};
+
+    fn tracker(name: &str) -> (UsageTracker, PathBuf) {
+        let path = temp_path("usage", &format!("{}.json", name));
+        (UsageTracker::open(&path).unwrap(), path)
+    }
+
+    /// A page of `lines` raster lines
+    fn page(label: &Label, lines: usize) -> PrintJob {
+        PrintJob::from_grayscale(&vec![0; 10 * lines], 10, label).unwrap()
+    }
+
+    #[test]
+    fn page_lengths() {
+        let label = label("62");
+        // 300 lines are an inch, plus the feed margins of the label before and after the page
+        let margins = 2.0 * label.feed_margin as f64 * 25.4 / 300.0;
+        assert!((page_length(&label, &page(&label, 300)) - (25.4 + margins)).abs() < 1e-9);
+
+        let mut job = page(&label, 300).with_resolution(Resolution::High);
+        assert!((page_length(&label, &job) - (25.4 + margins)).abs() < 1e-9);
+        job.feed_margin = Some(Margin::Millimeters(5.0));
+        assert!((page_length(&label, &job) - 35.4).abs() < 0.1);
+    }
+
+    #[test]
+    fn records_usage() {
+        let (tracker, path) = tracker("records");
+        let continuous = label("62");
+        let usage = tracker.record("A", &continuous, &vec![page(&continuous, 300); 2]).unwrap();
+        assert_eq!((usage.width, usage.length), (62, 0));
+        let length = 2.0 * page_length(&continuous, &page(&continuous, 300));
+        assert!((usage.millimeters_used - length).abs() < 1e-9);
+        assert!(usage.remaining().unwrap() < 1.0);
+
+        let die_cut = label("29x90");
+        let usage = tracker.record("B", &die_cut, &vec![page(&die_cut, 10); 3]).unwrap();
+        assert_eq!(usage.labels_used, 3);
+        assert_eq!(usage.label().unwrap().tape_size, die_cut.tape_size);
+
+        // Usage is kept in the file
+        let reopened = UsageTracker::open(&path).unwrap();
+        assert_eq!(reopened.all(), tracker.all());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn resets_when_the_media_changes() {
+        let (tracker, path) = tracker("media");
+        let die_cut = label("29x90");
+        tracker.record("A", &die_cut, &vec![page(&die_cut, 10); 3]).unwrap();
+        let other = label("62x29");
+        let usage = tracker.record("A", &other, &[page(&other, 10)]).unwrap();
+        assert_eq!((usage.width, usage.length), (62, 29));
+        assert_eq!(usage.labels_used, 1);
+
+        let usage = tracker.roll_replaced("A", Some(Roll::Labels(10))).unwrap();
+        assert_eq!(usage.labels_used, 0);
+        assert_eq!(usage.capacity, Some(Roll::Labels(10)));
+        assert!(tracker.roll_replaced("B", None).is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn thresholds() {
+        let (mut tracker, path) = tracker("thresholds");
+        assert!(tracker.crosses_threshold(0.25, 0.15));
+        assert!(tracker.crosses_threshold(0.2, 0.19));
+        assert!(tracker.crosses_threshold(0.1, 0.01));
+        assert!(!tracker.crosses_threshold(0.5, 0.3));
+        assert!(!tracker.crosses_threshold(0.15, 0.1));
+        assert!(!tracker.crosses_threshold(0.04, 0.0));
+        tracker.set_thresholds(&[0.5]);
+        assert!(tracker.crosses_threshold(0.5, 0.3));
+        assert!(!tracker.crosses_threshold(0.25, 0.15));
+
+        let die_cut = label("29x90");
+        tracker.record("A", &die_cut, &[page(&die_cut, 10)]).unwrap();
+        let usage = tracker.roll_replaced("A", Some(Roll::Labels(4))).unwrap();
+        assert_eq!(usage.remaining(), Some(1.0));
+        let usage = tracker.record("A", &die_cut, &vec![page(&die_cut, 10); 3]).unwrap();
+        assert_eq!(usage.remaining(), Some(0.25));
+        let usage = tracker.record("A", &die_cut, &vec![page(&die_cut, 10); 2]).unwrap();
+        assert_eq!(usage.remaining(), Some(0.0));
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,302 @@
+//! The JSON API
+//!
+//! * `GET /printers` lists the printers being served
+//! * `GET /printers/{serial}/status` asks a printer for its status. While it is printing, the status
+//!   it reported last is returned with `cached` set, along with the `job` in progress
+//! * `POST /printers/{serial}/jobs` queues a job, either a PNG image (`Content-Type: image/png`) or a
+//!   template filled in with data (`Content-Type: application/json`)
+//! * `GET /jobs/{id}` reports the state of a queued job
+//! * `GET /printers/{serial}/usage` estimates how much of the roll of a printer is left, if the
+//!   server tracks roll usage, and `POST /printers/{serial}/usage/reset` resets it once the roll was
+//!   replaced. The body may set what the new roll holds, e.g. `{"capacity": {"Labels": 400}}`
+//! * `GET /journal` lists the jobs recorded in the journal, if the server keeps one, optionally
+//!   filtered with the `printer`, `since`, `until`, `outcome` and `limit` query parameters
+//! * `GET /journal/{id}/preview` returns the PNG preview of a journal entry, if the server keeps them
+//! * `GET /metrics` returns the metrics of the printers for Prometheus to scrape
+//!
+//! Printers also speak IPP at `/ipp/print/{serial}`, see `ipp`.
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Cursor, Read};
+
+use brother_ql_rs::printer::catalogue::Roll;
+use brother_ql_rs::printer::constants::MAX_PIXEL_WIDTH;
+use brother_ql_rs::printer::journal::{Outcome, Query};
+use brother_ql_rs::printer::metrics;
+use brother_ql_rs::printer::usage::RollUsage;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response};
+
+use crate::ipp;
+use crate::queue::{self, Queue};
+use crate::render::{self, Image};
+
+/// Largest request body accepted, PNG images of labels are far smaller
+const MAX_BODY_SIZE: u64 = 16 * 1024 * 1024;
+/// Most copies of a template printed by one job
+const MAX_COPIES: usize = 100;
+/// Largest scale of template text, which makes characters 320 dots high
+const MAX_SCALE: usize = 10;
+/// Longest template text in dots, a metre of tape
+const MAX_TEXT_LENGTH: usize = 11_811;
+
+type ApiResponse = Response<Cursor<Vec<u8>>>;
+
+pub struct ApiError {
+    status: u16,
+    message: String,
+}
+
+impl ApiError {
+    pub fn new(status: u16, message: impl Into<String>) -> ApiError {
+        ApiError {
+            status,
+            message: message.into(),
+        }
+    }
+
+    fn not_found() -> ApiError {
+        ApiError::new(404, "Not found")
+    }
+}
+
+impl From<brother_ql_rs::printer::Error> for ApiError {
+    fn from(error: brother_ql_rs::printer::Error) -> ApiError {
+        ApiError::new(502, error.to_string())
+    }
+}
+
+/// A label template, printed once for every copy
+#[derive(Deserialize)]
+struct TemplateJob {
+    /// Text with `{field}` placeholders, lines separated by `\n`
+    template: String,
+    #[serde(default)]
+    data: HashMap<String, Value>,
+    /// Labels to print, up to `MAX_COPIES`
+    #[serde(default = "one")]
+    copies: usize,
+    /// Size of the text, each pixel of the 32 pixel high font is printed as `scale` by `scale` dots,
+    /// up to `MAX_SCALE`
+    #[serde(default = "one")]
+    scale: usize,
+}
+
+fn one() -> usize {
+    1
+}
+
+pub fn handle(queue: &Queue, mut request: Request) {
+    let response = match route(queue, &mut request) {
+        Ok(response) => response,
+        Err(error) => json_response(error.status, &json!({ "error": error.message })),
+    };
+    let _ = request.respond(response);
+}
+
+fn route(queue: &Queue, request: &mut Request) -> Result<ApiResponse, ApiError> {
+    let path = request.url().split('?').next().unwrap_or("").to_string();
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    match (request.method(), segments.as_slice()) {
+        (Method::Get, ["printers"]) => {
+            let printers: Vec<_> = queue.printers().map(|printer| &printer.info).collect();
+            Ok(json_response(200, &printers))
+        }
+        (Method::Get, ["printers", serial, "status"]) => {
+            let printer = queue.printer(serial).ok_or_else(ApiError::not_found)?;
+            Ok(json_response(200, &queue.status_report(printer)?))
+        }
+        (Method::Post, ["printers", serial, "jobs"]) => {
+            let printer = queue.printer(serial).ok_or_else(ApiError::not_found)?;
+            let pages = read_pages(request)?;
+            let pages = queue::lay_out(&printer.label()?, &pages)
+                .map_err(|error| ApiError::new(400, error.to_string()))?;
+            let job = queue.submit(printer, pages);
+            let location = Header::from_bytes("Location", format!("/jobs/{}", job.id)).unwrap();
+            Ok(json_response(202, &job).with_header(location))
+        }
+        (Method::Post, ["ipp", "print", serial]) => {
+            let printer = queue.printer(serial).ok_or_else(ApiError::not_found)?;
+            let host = header(request, "Host").unwrap_or_else(|| "localhost".to_string());
+            let body = read_body(request)?;
+            let content_type = Header::from_bytes("Content-Type", "application/ipp").unwrap();
+            Ok(Response::from_data(ipp::handle(queue, printer, &host, &body)).with_header(content_type))
+        }
+        (Method::Get, ["printers", serial, "usage"]) => {
+            let tracker = queue.usage.as_ref().ok_or_else(ApiError::not_found)?;
+            let usage = tracker.usage(serial).ok_or_else(|| no_usage(serial))?;
+            Ok(json_response(200, &usage_json(&usage)))
+        }
+        (Method::Post, ["printers", serial, "usage", "reset"]) => {
+            let tracker = queue.usage.as_ref().ok_or_else(ApiError::not_found)?;
+            tracker.usage(serial).ok_or_else(|| no_usage(serial))?;
+            let body = read_body(request)?;
+            let reset: RollReset = if body.is_empty() {
+                RollReset::default()
+            } else {
+                serde_json::from_slice(&body).map_err(|error| ApiError::new(400, error.to_string()))?
+            };
+            let usage = tracker.roll_replaced(serial, reset.capacity)?;
+            Ok(json_response(200, &usage_json(&usage)))
+        }
+        (Method::Get, ["jobs", id]) => {
+            let id = id.parse().map_err(|_| ApiError::not_found())?;
+            let job = queue.job(id).ok_or_else(ApiError::not_found)?;
+            Ok(json_response(200, &job))
+        }
+        (Method::Get, ["journal"]) => {
+            let journal = queue.journal.as_ref().ok_or_else(ApiError::not_found)?;
+            let query = journal_query(request.url())?;
+            Ok(json_response(200, &journal.query(&query)?))
+        }
+        (Method::Get, ["journal", id, "preview"]) => {
+            let journal = queue.journal.as_ref().ok_or_else(ApiError::not_found)?;
+            let id: u64 = id.parse().map_err(|_| ApiError::not_found())?;
+            let path = journal
+                .entries()?
+                .iter()
+                .find(|entry| entry.id == id)
+                .and_then(|entry| journal.preview_path(entry))
+                .ok_or_else(ApiError::not_found)?;
+            let image = fs::read(path).map_err(|_| ApiError::not_found())?;
+            let content_type = Header::from_bytes("Content-Type", "image/png").unwrap();
+            Ok(Response::from_data(image).with_header(content_type))
+        }
+        (Method::Get, ["metrics"]) => {
+            let content_type = Header::from_bytes("Content-Type", "text/plain; version=0.0.4").unwrap();
+            Ok(Response::from_data(metrics::render().into_bytes()).with_header(content_type))
+        }
+        (_, ["printers"])
+        | (_, ["printers", _, "status" | "jobs" | "usage"])
+        | (_, ["printers", _, "usage", "reset"])
+        | (_, ["ipp", "print", _])
+        | (_, ["jobs", _])
+        | (_, ["journal" | "metrics"])
+        | (_, ["journal", _, "preview"]) => {
+            Err(ApiError::new(405, "Method not allowed"))
+        }
+        _ => Err(ApiError::not_found()),
+    }
+}
+
+/// Body of a roll usage reset
+#[derive(Deserialize, Default)]
+struct RollReset {
+    capacity: Option<Roll>,
+}
+
+fn no_usage(serial: &str) -> ApiError {
+    ApiError::new(404, format!("Nothing was printed on printer {} yet", serial))
+}
+
+/// The usage of a roll along with the part of it that is left
+fn usage_json(usage: &RollUsage) -> Value {
+    let mut value = serde_json::to_value(usage).unwrap();
+    value["remaining"] = json!(usage.remaining());
+    value
+}
+
+/// Reads the filters of a journal query from the query string of `url`.
+fn journal_query(url: &str) -> Result<Query, ApiError> {
+    let mut query = Query::default();
+    let parameters = url.split_once('?').map_or("", |(_, parameters)| parameters);
+    for parameter in parameters.split('&').filter(|parameter| !parameter.is_empty()) {
+        let (name, value) = parameter.split_once('=').unwrap_or((parameter, ""));
+        let invalid = || ApiError::new(400, format!("Invalid value for {}: {}", name, value));
+        match name {
+            "printer" => query.serial_number = Some(value.to_string()),
+            "since" => query.since = Some(value.parse().map_err(|_| invalid())?),
+            "until" => query.until = Some(value.parse().map_err(|_| invalid())?),
+            "limit" => query.limit = Some(value.parse().map_err(|_| invalid())?),
+            "outcome" => {
+                query.outcome = Some(match value {
+                    "completed" => Outcome::Completed,
+                    "sent" => Outcome::Sent,
+                    "failed" => Outcome::Failed,
+                    _ => return Err(invalid()),
+                })
+            }
+            _ => return Err(ApiError::new(400, format!("Unknown query parameter {}", name))),
+        }
+    }
+    Ok(query)
+}
+
+fn header(request: &Request, name: &'static str) -> Option<String> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str().to_string())
+}
+
+fn read_body(request: &mut Request) -> Result<Vec<u8>, ApiError> {
+    let mut body = Vec::new();
+    request
+        .as_reader()
+        .take(MAX_BODY_SIZE)
+        .read_to_end(&mut body)
+        .map_err(|error| ApiError::new(400, error.to_string()))?;
+    Ok(body)
+}
+
+fn read_pages(request: &mut Request) -> Result<Vec<Image>, ApiError> {
+    let content_type = header(request, "Content-Type").unwrap_or_default();
+    let body = read_body(request)?;
+
+    match content_type.split(';').next().unwrap_or("").trim() {
+        "image/png" => {
+            let image = render::decode_png(&body)
+                .map_err(|error| ApiError::new(400, format!("Invalid PNG image: {}", error)))?;
+            Ok(vec![image])
+        }
+        "application/json" => {
+            let job: TemplateJob = serde_json::from_slice(&body)
+                .map_err(|error| ApiError::new(400, format!("Invalid job: {}", error)))?;
+            if !(1..=MAX_COPIES).contains(&job.copies) {
+                return Err(ApiError::new(
+                    400,
+                    format!("Copies must be between 1 and {}", MAX_COPIES),
+                ));
+            }
+            if !(1..=MAX_SCALE).contains(&job.scale) {
+                return Err(ApiError::new(
+                    400,
+                    format!("Scale must be between 1 and {}", MAX_SCALE),
+                ));
+            }
+            let data = job
+                .data
+                .iter()
+                .map(|(field, value)| {
+                    let value = match value {
+                        Value::String(text) => text.clone(),
+                        other => other.to_string(),
+                    };
+                    (field.clone(), value)
+                })
+                .collect();
+            let text = render::fill_template(&job.template, &data);
+            let (width, height) = render::text_size(&text, job.scale);
+            if width > MAX_PIXEL_WIDTH || height > MAX_TEXT_LENGTH {
+                return Err(ApiError::new(
+                    400,
+                    format!("The text is {}x{} dots, too large for any label", width, height),
+                ));
+            }
+            Ok(vec![render::render_text(&text, job.scale); job.copies])
+        }
+        _ => Err(ApiError::new(
+            415,
+            "Jobs must be sent as image/png or application/json",
+        )),
+    }
+}
+
+fn json_response<T: serde::Serialize + ?Sized>(status: u16, body: &T) -> ApiResponse {
+    let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+    Response::from_data(serde_json::to_vec(body).unwrap())
+        .with_status_code(status)
+        .with_header(content_type)
+}
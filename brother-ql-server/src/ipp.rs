@@ -0,0 +1,766 @@
+//! A minimal IPP/2.0 printer, so desktops can print labels without drivers
+//!
+//! Every printer is served at `ipp://{host}/ipp/print/{serial}` and supports the Print-Job,
+//! Validate-Job, Get-Jobs, Get-Job-Attributes and Get-Printer-Attributes operations. Documents can be
+//! PWG raster or PNG images, each page becoming one label.
+use std::sync::LazyLock;
+use std::time::Instant;
+
+use brother_ql_rs::printer::constants::{known_labels, Label};
+use brother_ql_rs::printer::job::PrintJob;
+use brother_ql_rs::printer::raster;
+use brother_ql_rs::printer::spooler::JobState;
+use brother_ql_rs::printer::status::Response;
+
+use crate::queue::{self, JobInfo, Printer, PrinterInfo, Queue};
+use crate::render::{self, Image};
+
+/// Pages sent in a single job may be printed at most this many times
+const MAX_COPIES: i32 = 100;
+/// Most raster lines printed by a single job, including copies, about 17 m of tape
+const MAX_JOB_LINES: usize = 200_000;
+/// Largest page accepted, once it was scaled to the resolution of the print head
+const MAX_PAGE_PIXELS: usize = 1 << 26;
+/// Longest label printed on continuous tape, in hundredths of a millimeter
+const MAX_CONTINUOUS_LENGTH: i32 = 100_000;
+
+static STARTED: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+mod group {
+    pub const OPERATION: u8 = 0x01;
+    pub const JOB: u8 = 0x02;
+    pub const END: u8 = 0x03;
+    pub const PRINTER: u8 = 0x04;
+}
+
+mod tag {
+    pub const INTEGER: u8 = 0x21;
+    pub const BOOLEAN: u8 = 0x22;
+    pub const ENUM: u8 = 0x23;
+    pub const RESOLUTION: u8 = 0x32;
+    pub const RANGE: u8 = 0x33;
+    pub const BEGIN_COLLECTION: u8 = 0x34;
+    pub const END_COLLECTION: u8 = 0x37;
+    pub const TEXT: u8 = 0x41;
+    pub const NAME: u8 = 0x42;
+    pub const KEYWORD: u8 = 0x44;
+    pub const URI: u8 = 0x45;
+    pub const CHARSET: u8 = 0x47;
+    pub const LANGUAGE: u8 = 0x48;
+    pub const MIME_TYPE: u8 = 0x49;
+    pub const MEMBER_NAME: u8 = 0x4A;
+}
+
+mod operation {
+    pub const PRINT_JOB: u16 = 0x0002;
+    pub const VALIDATE_JOB: u16 = 0x0004;
+    pub const GET_JOB_ATTRIBUTES: u16 = 0x0009;
+    pub const GET_JOBS: u16 = 0x000A;
+    pub const GET_PRINTER_ATTRIBUTES: u16 = 0x000B;
+}
+
+mod status {
+    pub const OK: u16 = 0x0000;
+    pub const BAD_REQUEST: u16 = 0x0400;
+    pub const NOT_FOUND: u16 = 0x0406;
+    pub const REQUEST_ENTITY_TOO_LARGE: u16 = 0x0408;
+    pub const DOCUMENT_FORMAT_NOT_SUPPORTED: u16 = 0x040A;
+    pub const DOCUMENT_FORMAT_ERROR: u16 = 0x0411;
+    pub const OPERATION_NOT_SUPPORTED: u16 = 0x0501;
+    pub const VERSION_NOT_SUPPORTED: u16 = 0x0503;
+    pub const DEVICE_ERROR: u16 = 0x0504;
+}
+
+const DOCUMENT_FORMATS: [&str; 3] = ["application/octet-stream", "image/png", "image/pwg-raster"];
+
+struct Attribute {
+    group: u8,
+    name: String,
+    values: Vec<Vec<u8>>,
+}
+
+struct Message<'a> {
+    version: u8,
+    operation: u16,
+    request_id: u32,
+    attributes: Vec<Attribute>,
+    data: &'a [u8],
+}
+
+impl<'a> Message<'a> {
+    fn parse(body: &'a [u8]) -> Option<Message<'a>> {
+        let mut reader = Reader { body, position: 0 };
+        let header = reader.take(8)?;
+        let mut message = Message {
+            version: header[0],
+            operation: u16::from_be_bytes([header[2], header[3]]),
+            request_id: u32::from_be_bytes([header[4], header[5], header[6], header[7]]),
+            attributes: Vec::new(),
+            data: &[],
+        };
+        let mut group = 0;
+        loop {
+            let tag = reader.take(1)?[0];
+            if tag == group::END {
+                break;
+            }
+            if tag < 0x10 {
+                group = tag;
+                continue;
+            }
+            let name = reader.sized()?;
+            let value = reader.sized()?.to_vec();
+            match message.attributes.last_mut() {
+                // Additional values, and the members of collections, have no name
+                Some(attribute) if name.is_empty() => attribute.values.push(value),
+                _ => message.attributes.push(Attribute {
+                    group,
+                    name: String::from_utf8_lossy(name).into_owned(),
+                    values: vec![value],
+                }),
+            }
+        }
+        message.data = &body[reader.position..];
+        Some(message)
+    }
+
+    fn attribute(&self, group: u8, name: &str) -> Option<&[u8]> {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.group == group && attribute.name == name)
+            .and_then(|attribute| attribute.values.first())
+            .map(|value| value.as_slice())
+    }
+
+    fn string(&self, group: u8, name: &str) -> Option<String> {
+        self.attribute(group, name)
+            .map(|value| String::from_utf8_lossy(value).into_owned())
+    }
+
+    fn integer(&self, group: u8, name: &str) -> Option<i32> {
+        let value = self.attribute(group, name)?;
+        Some(i32::from_be_bytes(value.try_into().ok()?))
+    }
+}
+
+struct Reader<'a> {
+    body: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Option<&'a [u8]> {
+        let bytes = self.body.get(self.position..self.position + length)?;
+        self.position += length;
+        Some(bytes)
+    }
+
+    /// Reads a value preceded by its length.
+    fn sized(&mut self) -> Option<&'a [u8]> {
+        let length = self.take(2)?;
+        self.take(u16::from_be_bytes([length[0], length[1]]) as usize)
+    }
+}
+
+struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    fn new(status: u16, request_id: u32) -> Writer {
+        let mut writer = Writer { data: vec![2, 0] };
+        writer.data.extend_from_slice(&status.to_be_bytes());
+        writer.data.extend_from_slice(&request_id.to_be_bytes());
+        writer.group(group::OPERATION);
+        writer.value(tag::CHARSET, "attributes-charset", b"utf-8");
+        writer.value(tag::LANGUAGE, "attributes-natural-language", b"en");
+        writer
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.data.push(group::END);
+        self.data
+    }
+
+    fn group(&mut self, group: u8) {
+        self.data.push(group);
+    }
+
+    fn value(&mut self, tag: u8, name: &str, value: &[u8]) {
+        self.data.push(tag);
+        self.data.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        self.data.extend_from_slice(name.as_bytes());
+        self.data.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        self.data.extend_from_slice(value);
+    }
+
+    /// Writes an attribute with several values, only the first of which carries the name.
+    fn values<T: AsRef<[u8]>>(&mut self, tag: u8, name: &str, values: &[T]) {
+        for (index, value) in values.iter().enumerate() {
+            self.value(tag, if index == 0 { name } else { "" }, value.as_ref());
+        }
+    }
+
+    fn integer(&mut self, tag: u8, name: &str, value: i32) {
+        self.value(tag, name, &value.to_be_bytes());
+    }
+
+    fn range(&mut self, name: &str, lower: i32, upper: i32) {
+        let mut value = lower.to_be_bytes().to_vec();
+        value.extend_from_slice(&upper.to_be_bytes());
+        self.value(tag::RANGE, name, &value);
+    }
+
+    fn collection<F: FnOnce(&mut Writer)>(&mut self, name: &str, members: F) {
+        self.value(tag::BEGIN_COLLECTION, name, &[]);
+        members(self);
+        self.value(tag::END_COLLECTION, "", &[]);
+    }
+
+    fn member(&mut self, name: &str) {
+        self.value(tag::MEMBER_NAME, "", name.as_bytes());
+    }
+
+    fn media_size(&mut self, name: &str, label: &Label) {
+        self.collection(name, |writer| {
+            writer.member("x-dimension");
+            writer.integer(tag::INTEGER, "", label.tape_size.0 as i32 * 100);
+            writer.member("y-dimension");
+            if label.is_die_cut() {
+                writer.integer(tag::INTEGER, "", label.tape_size.1 as i32 * 100);
+            } else {
+                writer.range("", 1, MAX_CONTINUOUS_LENGTH);
+            }
+        });
+    }
+
+    fn media_col(&mut self, name: &str, label: &Label) {
+        self.collection(name, |writer| {
+            writer.member("media-size");
+            writer.media_size("", label);
+        });
+    }
+
+    fn job(&mut self, printer_uri: &str, job: &JobInfo) {
+        let (state, reason) = match job.state {
+            JobState::Queued => (3, "none"),
+            JobState::Printing { .. } => (5, "job-printing"),
+            JobState::WaitingForRecovery(_) => (6, "printer-stopped"),
+            JobState::Completed => (9, "job-completed-successfully"),
+            JobState::Failed(_) => (8, "aborted-by-system"),
+        };
+        self.group(group::JOB);
+        self.integer(tag::INTEGER, "job-id", job.id as i32);
+        self.value(tag::URI, "job-uri", job_uri(printer_uri, job).as_bytes());
+        self.value(tag::URI, "job-printer-uri", printer_uri.as_bytes());
+        self.integer(tag::ENUM, "job-state", state);
+        self.value(tag::KEYWORD, "job-state-reasons", reason.as_bytes());
+        if let Some(error) = &job.error {
+            self.value(tag::TEXT, "job-state-message", error.as_bytes());
+        }
+    }
+}
+
+fn job_uri(printer_uri: &str, job: &JobInfo) -> String {
+    format!("{}/{}", printer_uri, job.id)
+}
+
+/// Answers an IPP request addressed to `printer`, reachable under `host`.
+pub fn handle(queue: &Queue, printer: &Printer, host: &str, body: &[u8]) -> Vec<u8> {
+    let message = match Message::parse(body) {
+        Some(message) => message,
+        None => return Writer::new(status::BAD_REQUEST, 0).finish(),
+    };
+    if message.version != 1 && message.version != 2 {
+        return Writer::new(status::VERSION_NOT_SUPPORTED, message.request_id).finish();
+    }
+    let printer_uri = format!("ipp://{}/ipp/print/{}", host, printer.info.serial);
+    match message.operation {
+        operation::GET_PRINTER_ATTRIBUTES => {
+            printer_attributes(queue, printer, &printer_uri, message.request_id)
+        }
+        operation::PRINT_JOB => print_job(queue, printer, &printer_uri, &message),
+        operation::VALIDATE_JOB => match document_pages(&message, false) {
+            Ok(_) => Writer::new(status::OK, message.request_id).finish(),
+            Err(status) => Writer::new(status, message.request_id).finish(),
+        },
+        operation::GET_JOBS => get_jobs(&message, &printer_uri, &queue.jobs(&printer.info.serial)),
+        operation::GET_JOB_ATTRIBUTES => {
+            let job = message
+                .integer(group::OPERATION, "job-id")
+                .and_then(|id| queue.job(id as u64))
+                .filter(|job| job.printer == printer.info.serial);
+            match job {
+                Some(job) => {
+                    let mut writer = Writer::new(status::OK, message.request_id);
+                    writer.job(&printer_uri, &job);
+                    writer.finish()
+                }
+                None => Writer::new(status::NOT_FOUND, message.request_id).finish(),
+            }
+        }
+        _ => Writer::new(status::OPERATION_NOT_SUPPORTED, message.request_id).finish(),
+    }
+}
+
+/// Lists the jobs of a printer, newest first, filtered by the `which-jobs` and `limit` attributes of
+/// a Get-Jobs request.
+fn get_jobs(message: &Message, printer_uri: &str, jobs: &[JobInfo]) -> Vec<u8> {
+    let which = message.string(group::OPERATION, "which-jobs");
+    let limit = message
+        .integer(group::OPERATION, "limit")
+        .map_or(usize::MAX, |limit| limit.max(0) as usize);
+    let mut writer = Writer::new(status::OK, message.request_id);
+    for job in jobs
+        .iter()
+        .rev()
+        .filter(|job| {
+            let finished = job.state.is_finished();
+            match which.as_deref() {
+                Some("completed") => finished,
+                Some("all") => true,
+                _ => !finished,
+            }
+        })
+        .take(limit)
+    {
+        writer.job(printer_uri, job);
+    }
+    writer.finish()
+}
+
+fn print_job(queue: &Queue, printer: &Printer, printer_uri: &str, message: &Message) -> Vec<u8> {
+    let label = match printer.label() {
+        Ok(label) => label,
+        Err(_) => return Writer::new(status::DEVICE_ERROR, message.request_id).finish(),
+    };
+    let pages = match job_pages(message, &label) {
+        Ok(pages) => pages,
+        Err(status) => return Writer::new(status, message.request_id).finish(),
+    };
+
+    let job = queue.submit(printer, pages);
+    let mut writer = Writer::new(status::OK, message.request_id);
+    writer.job(printer_uri, &job);
+    writer.finish()
+}
+
+/// Lays out the document of a Print-Job request for `label`, repeated for the copies it asks for.
+///
+/// Every page is laid out once and its copies share the result. Jobs printing more than
+/// `MAX_JOB_LINES` raster lines in total are rejected before any copies are made.
+fn job_pages(message: &Message, label: &Label) -> Result<Vec<PrintJob>, u16> {
+    let copies = message
+        .integer(group::JOB, "copies")
+        .unwrap_or(1)
+        .clamp(1, MAX_COPIES) as usize;
+    let mut document = Vec::new();
+    let mut lines: usize = 0;
+    for (image, page_copies) in document_pages(message, true)? {
+        let page = queue::lay_out_page(label, &image).map_err(|_| status::DOCUMENT_FORMAT_ERROR)?;
+        lines = lines.saturating_add(page.raster_lines.len().saturating_mul(page_copies));
+        document.push((page, page_copies));
+    }
+    if lines.saturating_mul(copies) > MAX_JOB_LINES {
+        return Err(status::REQUEST_ENTITY_TOO_LARGE);
+    }
+
+    let mut pages = Vec::new();
+    for _ in 0..copies {
+        for (page, page_copies) in &document {
+            pages.extend(std::iter::repeat_n(page, *page_copies).cloned());
+        }
+    }
+    Ok(pages)
+}
+
+/// Decodes the document of a Print-Job request into its pages and how often each is printed, or only
+/// checks its format unless `decode` is set.
+fn document_pages(message: &Message, decode: bool) -> Result<Vec<(Image, usize)>, u16> {
+    let format = message
+        .string(group::OPERATION, "document-format")
+        .unwrap_or_else(|| DOCUMENT_FORMATS[0].to_string());
+    let format = match format.as_str() {
+        "application/octet-stream" if decode => sniff(message.data),
+        "application/octet-stream" => return Ok(Vec::new()),
+        other => other,
+    };
+    if !decode && DOCUMENT_FORMATS.contains(&format) {
+        return Ok(Vec::new());
+    }
+    match format {
+        "image/png" => render::decode_png(message.data)
+            .map(|image| vec![(image, 1)])
+            .map_err(|_| status::DOCUMENT_FORMAT_ERROR),
+        "image/pwg-raster" => {
+            let pages = raster::read_pages(message.data).map_err(|_| status::DOCUMENT_FORMAT_ERROR)?;
+            let mut images = Vec::new();
+            for page in pages {
+                let (width, height) = Image::raster_size(&page);
+                if width.saturating_mul(height) > MAX_PAGE_PIXELS {
+                    return Err(status::REQUEST_ENTITY_TOO_LARGE);
+                }
+                let copies = (page.copies as usize).min(MAX_COPIES as usize);
+                images.push((Image::from_raster(page), copies));
+            }
+            Ok(images)
+        }
+        _ => Err(status::DOCUMENT_FORMAT_NOT_SUPPORTED),
+    }
+}
+
+fn sniff(data: &[u8]) -> &'static str {
+    match data.get(..4) {
+        Some(b"\x89PNG") => "image/png",
+        Some(b"RaS2" | b"2SaR" | b"RaS3" | b"3SaR") => "image/pwg-raster",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Encodes a resolution value in dots per inch.
+fn resolution(dpi: i32) -> Vec<u8> {
+    const DOTS_PER_INCH: u8 = 3;
+    let mut value = dpi.to_be_bytes().to_vec();
+    value.extend_from_slice(&dpi.to_be_bytes());
+    value.push(DOTS_PER_INCH);
+    value
+}
+
+/// PWG media size name, such as `om_29x90mm_29x90mm` for die-cut labels
+fn media_name(label: &Label) -> String {
+    let (width, length) = (label.tape_size.0, label.tape_size.1);
+    if label.is_die_cut() {
+        format!("om_{}x{}mm_{}x{}mm", width, length, width, length)
+    } else {
+        format!(
+            "roll_{}mm_{}x{}mm",
+            width,
+            width,
+            MAX_CONTINUOUS_LENGTH / 100
+        )
+    }
+}
+
+fn printer_attributes(queue: &Queue, printer: &Printer, printer_uri: &str, request_id: u32) -> Vec<u8> {
+    let queued = queue
+        .jobs(&printer.info.serial)
+        .iter()
+        .filter(|job| !job.state.is_finished())
+        .count();
+    let status = printer.status_if_idle();
+    let loaded = printer.last_known_label();
+    write_printer_attributes(&printer.info, status, loaded, queued, printer_uri, request_id)
+}
+
+/// Encodes the attributes of a printer with the given status, which is `None` while it is printing.
+fn write_printer_attributes(
+    info: &PrinterInfo,
+    status: Option<brother_ql_rs::printer::Result<Response>>,
+    loaded: Option<Label>,
+    queued: usize,
+    printer_uri: &str,
+    request_id: u32,
+) -> Vec<u8> {
+    let (state, reasons, message) = match status {
+        None => (4, vec!["none"], None),
+        Some(Err(error)) => (5, vec!["other-error"], Some(error.to_string())),
+        Some(Ok(status)) if status.errors.is_empty() => (3, vec!["none"], None),
+        Some(Ok(status)) => {
+            let mut reasons: Vec<&str> = status
+                .errors
+                .iter()
+                .map(|error| match *error {
+                    "No media when printing" | "End of media" => "media-empty-error",
+                    "Tape cutter jam" => "media-jam-error",
+                    "Cover open" => "cover-open-error",
+                    _ => "other-error",
+                })
+                .collect();
+            reasons.dedup();
+            (5, reasons, Some(status.errors.join(", ")))
+        }
+    };
+    let labels = known_labels();
+
+    let mut writer = Writer::new(status::OK, request_id);
+    writer.group(group::PRINTER);
+    writer.value(tag::URI, "printer-uri-supported", printer_uri.as_bytes());
+    writer.value(tag::KEYWORD, "uri-security-supported", b"none");
+    writer.value(tag::KEYWORD, "uri-authentication-supported", b"none");
+    writer.value(tag::NAME, "printer-name", format!("{} {}", info.model, info.serial).as_bytes());
+    writer.value(
+        tag::TEXT,
+        "printer-make-and-model",
+        format!("{} {}", info.manufacturer, info.model).as_bytes(),
+    );
+    writer.integer(tag::ENUM, "printer-state", state);
+    writer.values(tag::KEYWORD, "printer-state-reasons", &reasons);
+    if let Some(message) = message {
+        writer.value(tag::TEXT, "printer-state-message", message.as_bytes());
+    }
+    writer.value(tag::BOOLEAN, "printer-is-accepting-jobs", &[1]);
+    writer.integer(tag::INTEGER, "queued-job-count", queued as i32);
+    writer.integer(tag::INTEGER, "printer-up-time", STARTED.elapsed().as_secs() as i32 + 1);
+    writer.values(tag::KEYWORD, "ipp-versions-supported", &["1.1", "2.0"]);
+    let operations = [
+        operation::PRINT_JOB,
+        operation::VALIDATE_JOB,
+        operation::GET_JOB_ATTRIBUTES,
+        operation::GET_JOBS,
+        operation::GET_PRINTER_ATTRIBUTES,
+    ]
+    .map(|operation| (operation as i32).to_be_bytes());
+    writer.values(tag::ENUM, "operations-supported", &operations);
+    writer.value(tag::CHARSET, "charset-configured", b"utf-8");
+    writer.value(tag::CHARSET, "charset-supported", b"utf-8");
+    writer.value(tag::LANGUAGE, "natural-language-configured", b"en");
+    writer.value(tag::LANGUAGE, "generated-natural-language-supported", b"en");
+    writer.value(tag::MIME_TYPE, "document-format-default", DOCUMENT_FORMATS[0].as_bytes());
+    writer.values(tag::MIME_TYPE, "document-format-supported", &DOCUMENT_FORMATS);
+    writer.value(tag::KEYWORD, "pdl-override-supported", b"not-attempted");
+    writer.value(tag::KEYWORD, "compression-supported", b"none");
+    writer.value(tag::BOOLEAN, "color-supported", &[0]);
+    writer.value(tag::KEYWORD, "print-color-mode-default", b"monochrome");
+    writer.value(tag::KEYWORD, "print-color-mode-supported", b"monochrome");
+    writer.value(tag::KEYWORD, "sides-default", b"one-sided");
+    writer.value(tag::KEYWORD, "sides-supported", b"one-sided");
+    writer.integer(tag::INTEGER, "copies-default", 1);
+    writer.range("copies-supported", 1, MAX_COPIES);
+    let resolution = resolution(300);
+    writer.value(tag::RESOLUTION, "printer-resolution-default", &resolution);
+    writer.value(tag::RESOLUTION, "printer-resolution-supported", &resolution);
+    writer.value(tag::RESOLUTION, "pwg-raster-document-resolution-supported", &resolution);
+    writer.values(tag::KEYWORD, "pwg-raster-document-type-supported", &["black_1", "sgray_8"]);
+    writer.value(tag::KEYWORD, "pwg-raster-document-sheet-back", b"normal");
+    for side in ["bottom", "left", "right", "top"] {
+        // Pages are cropped to the printable area of the label
+        writer.integer(tag::INTEGER, &format!("media-{}-margin-supported", side), 0);
+    }
+
+    let names: Vec<String> = labels.iter().map(media_name).collect();
+    writer.values(tag::KEYWORD, "media-supported", &names);
+    for (index, label) in labels.iter().enumerate() {
+        writer.media_size(if index == 0 { "media-size-supported" } else { "" }, label);
+    }
+    if let Some(label) = loaded {
+        let name = media_name(&label);
+        writer.value(tag::KEYWORD, "media-default", name.as_bytes());
+        writer.value(tag::KEYWORD, "media-ready", name.as_bytes());
+        writer.media_col("media-col-default", &label);
+        writer.media_col("media-col-ready", &label);
+    }
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use brother_ql_rs::printer::constants::label_data;
+
+    /// Builds a request, which is laid out like a response with the operation in place of the status.
+    fn request(operation: u16, attributes: impl FnOnce(&mut Writer), data: &[u8]) -> Vec<u8> {
+        let mut writer = Writer::new(operation, 7);
+        attributes(&mut writer);
+        let mut body = writer.finish();
+        body.extend_from_slice(data);
+        body
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut data, width, height);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer
+            .write_image_data(&vec![0; (width * height) as usize])
+            .unwrap();
+        writer.finish().unwrap();
+        data
+    }
+
+    /// An uncompressed 8-bit grayscale PWG raster page at 300 dpi
+    fn pwg(width: u32, height: u32, copies: u32) -> Vec<u8> {
+        let mut header = vec![0; 1796];
+        let mut field = |offset: usize, value: u32| {
+            header[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+        };
+        field(276, 300);
+        field(280, 300);
+        field(340, copies);
+        field(372, width);
+        field(376, height);
+        field(384, 8);
+        field(388, 8);
+        field(392, width);
+        field(400, 18);
+        let mut stream = b"RaS3".to_vec();
+        stream.extend(header);
+        stream.extend(vec![0; (width * height) as usize]);
+        stream
+    }
+
+    fn print_request(format: &str, copies: i32, data: &[u8]) -> Vec<u8> {
+        request(
+            operation::PRINT_JOB,
+            |writer| {
+                writer.value(tag::MIME_TYPE, "document-format", format.as_bytes());
+                writer.group(group::JOB);
+                writer.integer(tag::INTEGER, "copies", copies);
+            },
+            data,
+        )
+    }
+
+    fn job(id: u64, state: JobState) -> JobInfo {
+        JobInfo {
+            id,
+            printer: "A1B2".to_string(),
+            pages: 1,
+            state,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn parses_requests() {
+        let body = print_request("image/png", 3, b"data");
+        let message = Message::parse(&body).unwrap();
+        assert_eq!(message.version, 2);
+        assert_eq!(message.operation, operation::PRINT_JOB);
+        assert_eq!(message.request_id, 7);
+        assert_eq!(
+            message.string(group::OPERATION, "document-format").as_deref(),
+            Some("image/png")
+        );
+        assert_eq!(message.integer(group::JOB, "copies"), Some(3));
+        assert_eq!(message.integer(group::OPERATION, "copies"), None);
+        assert_eq!(message.data, b"data");
+
+        // Without the end of the attributes
+        let end = body.len() - 5;
+        assert!(Message::parse(&body[..end]).is_none());
+        assert!(Message::parse(&body[..4]).is_none());
+    }
+
+    #[test]
+    fn lays_out_print_jobs_with_copies() {
+        let label = label_data(62, None).unwrap();
+        let body = print_request("image/png", 3, &png(696, 20));
+        let pages = job_pages(&Message::parse(&body).unwrap(), &label).unwrap();
+        assert_eq!(pages.len(), 3);
+
+        // Copies of every page, for every copy of the job
+        let body = print_request("application/octet-stream", 2, &pwg(696, 20, 4));
+        let pages = job_pages(&Message::parse(&body).unwrap(), &label).unwrap();
+        assert_eq!(pages.len(), 8);
+    }
+
+    #[test]
+    fn rejects_print_jobs() {
+        let label = label_data(62, None).unwrap();
+        let pages = |body: Vec<u8>| job_pages(&Message::parse(&body).unwrap(), &label).err();
+
+        // Too many lines once the copies of the pages and of the job are counted
+        let body = print_request("image/pwg-raster", MAX_COPIES, &pwg(696, 30, MAX_COPIES as u32));
+        assert_eq!(pages(body), Some(status::REQUEST_ENTITY_TOO_LARGE));
+        assert_eq!(
+            pages(print_request("image/jpeg", 1, b"")),
+            Some(status::DOCUMENT_FORMAT_NOT_SUPPORTED)
+        );
+        assert_eq!(
+            pages(print_request("image/png", 1, b"not a png")),
+            Some(status::DOCUMENT_FORMAT_ERROR)
+        );
+    }
+
+    #[test]
+    fn encodes_print_job_responses() {
+        let mut writer = Writer::new(status::OK, 7);
+        writer.job("ipp://host/ipp/print/A1B2", &job(5, JobState::Queued));
+        let body = writer.finish();
+        let response = Message::parse(&body).unwrap();
+        assert_eq!(response.operation, status::OK);
+        assert_eq!(response.request_id, 7);
+        assert_eq!(response.integer(group::JOB, "job-id"), Some(5));
+        assert_eq!(
+            response.string(group::JOB, "job-uri").as_deref(),
+            Some("ipp://host/ipp/print/A1B2/5")
+        );
+        assert_eq!(response.integer(group::JOB, "job-state"), Some(3));
+    }
+
+    #[test]
+    fn lists_jobs() {
+        let jobs = [
+            job(1, JobState::Completed),
+            job(2, JobState::Failed("Cover open".to_string())),
+            job(3, JobState::Printing { attempt: 1 }),
+            job(4, JobState::Queued),
+        ];
+        let ids = |attributes: &dyn Fn(&mut Writer)| {
+            let body = request(operation::GET_JOBS, attributes, &[]);
+            let response = get_jobs(&Message::parse(&body).unwrap(), "ipp://host", &jobs);
+            let response = Message::parse(&response).unwrap();
+            response
+                .attributes
+                .iter()
+                .filter(|attribute| attribute.name == "job-id")
+                .map(|attribute| i32::from_be_bytes(attribute.values[0][..].try_into().unwrap()))
+                .collect::<Vec<_>>()
+        };
+
+        // Unfinished jobs by default, newest first
+        assert_eq!(ids(&|_| {}), [4, 3]);
+        assert_eq!(
+            ids(&|writer| writer.value(tag::KEYWORD, "which-jobs", b"completed")),
+            [2, 1]
+        );
+        assert_eq!(
+            ids(&|writer| {
+                writer.value(tag::KEYWORD, "which-jobs", b"all");
+                writer.integer(tag::INTEGER, "limit", 3);
+            }),
+            [4, 3, 2]
+        );
+    }
+
+    #[test]
+    fn encodes_printer_attributes() {
+        let info = PrinterInfo {
+            serial: "A1B2".to_string(),
+            manufacturer: "Brother".to_string(),
+            model: "QL-700".to_string(),
+        };
+        let label = label_data(62, None);
+        let attributes = |status, loaded| {
+            write_printer_attributes(&info, status, loaded, 2, "ipp://host/ipp/print/A1B2", 7)
+        };
+
+        let body = attributes(None, label);
+        let response = Message::parse(&body).unwrap();
+        assert_eq!(response.request_id, 7);
+        assert_eq!(response.integer(group::PRINTER, "printer-state"), Some(4));
+        assert_eq!(response.integer(group::PRINTER, "queued-job-count"), Some(2));
+        assert_eq!(
+            response.string(group::PRINTER, "media-ready").as_deref(),
+            Some("roll_62mm_62x1000mm")
+        );
+
+        let mut status = [0; 32];
+        status[0] = 0x80;
+        status[9] = 0x10;
+        let body = attributes(Some(Response::from_bytes(&status)), None);
+        let response = Message::parse(&body).unwrap();
+        assert_eq!(response.integer(group::PRINTER, "printer-state"), Some(5));
+        assert_eq!(
+            response.string(group::PRINTER, "printer-state-reasons").as_deref(),
+            Some("cover-open-error")
+        );
+        assert_eq!(
+            response.string(group::PRINTER, "printer-state-message").as_deref(),
+            Some("Cover open")
+        );
+        assert_eq!(response.string(group::PRINTER, "media-ready"), None);
+    }
+}
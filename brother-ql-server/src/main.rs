@@ -0,0 +1,106 @@
+//! HTTP print server for Brother QL printers
+//!
+//! Serves every printer attached over USB when the server starts, see `api` for the endpoints and
+//! `ipp` for printing from desktops.
+//!
+//! Usage: `brother-ql-server [--listen ADDRESS] [--journal FILE [--journal-previews DIR]] [--usage FILE]`,
+//! listening on 127.0.0.1:8080 by default. With `--journal`, every job is recorded in a journal file,
+//! see `brother_ql_rs::printer::journal`, and with `--journal-previews` a PNG preview of every job is
+//! kept in a directory as well. With `--usage`, how much of the roll of every printer is left
+//! is estimated and kept in a file, see `brother_ql_rs::printer::usage`.
+use std::env;
+use std::process;
+use std::sync::Arc;
+use std::thread;
+
+use brother_ql_rs::printer::journal::Journal;
+use brother_ql_rs::printer::usage::UsageTracker;
+use brother_ql_rs::printer::{printers, ThermalPrinter};
+use log::{error, info, warn};
+
+mod api;
+mod ipp;
+mod queue;
+mod render;
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:8080";
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let mut address = DEFAULT_ADDRESS.to_string();
+    let mut journal = None;
+    let mut previews = None;
+    let mut usage = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--listen", Some(value)) => address = value,
+            ("--journal", Some(value)) => journal = Some(value),
+            ("--journal-previews", Some(value)) => previews = Some(value),
+            ("--usage", Some(value)) => usage = Some(value),
+            _ => usage_error(),
+        }
+    }
+    if previews.is_some() && journal.is_none() {
+        usage_error();
+    }
+
+    let mut queue = queue::Queue::default();
+    if let Some(path) = journal {
+        let journal = match &previews {
+            Some(directory) => Journal::with_previews(&path, directory),
+            None => Journal::open(&path),
+        };
+        match journal {
+            Ok(journal) => queue.journal = Some(Arc::new(journal)),
+            Err(error) => {
+                error!("Can't open journal {}: {}", path, error);
+                process::exit(1);
+            }
+        }
+    }
+    if let Some(path) = usage {
+        match UsageTracker::open(&path) {
+            Ok(tracker) => queue.usage = Some(Arc::new(tracker)),
+            Err(error) => {
+                error!("Can't open usage file {}: {}", path, error);
+                process::exit(1);
+            }
+        }
+    }
+    for device in printers() {
+        match ThermalPrinter::new(device) {
+            Ok(printer) => {
+                if let Err(error) = queue.add_printer(printer) {
+                    warn!("Skipping printer: {}", error);
+                }
+            }
+            Err(error) => warn!("Skipping printer: {}", error),
+        }
+    }
+    if queue.printers().next().is_none() {
+        warn!("No printers found");
+    }
+    let queue = Arc::new(queue);
+
+    let server = match tiny_http::Server::http(&address) {
+        Ok(server) => server,
+        Err(error) => {
+            error!("Can't listen on {}: {}", address, error);
+            process::exit(1);
+        }
+    };
+    info!("Listening on http://{}", address);
+    for request in server.incoming_requests() {
+        let queue = queue.clone();
+        thread::spawn(move || api::handle(&queue, request));
+    }
+}
+
+fn usage_error() -> ! {
+    eprintln!(
+        "Usage: brother-ql-server [--listen ADDRESS] [--journal FILE [--journal-previews DIR]] [--usage FILE]"
+    );
+    process::exit(2);
+}
@@ -0,0 +1,284 @@
+//! The printers served and the jobs sent to them
+//!
+//! Every printer has a `Spooler` printing its jobs one after another, so requests never have to wait
+//! for a job to be printed. Jobs are laid out for the label loaded when they are submitted.
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use brother_ql_rs::printer::constants::Label;
+use brother_ql_rs::printer::job::PrintJob;
+use brother_ql_rs::printer::journal::Journal;
+use brother_ql_rs::printer::setting::Resolution;
+use brother_ql_rs::printer::spooler::{JobHandle, JobState, Spooler};
+use brother_ql_rs::printer::usage::UsageTracker;
+use brother_ql_rs::printer::{status, ThermalPrinter};
+use log::info;
+use rusb::GlobalContext;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::render::Image;
+
+/// How many finished jobs are remembered for polling
+const FINISHED_JOBS_KEPT: usize = 1000;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PrinterInfo {
+    pub serial: String,
+    pub manufacturer: String,
+    pub model: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobInfo {
+    pub id: u64,
+    pub printer: String,
+    pub pages: usize,
+    #[serde(serialize_with = "serialize_state")]
+    pub state: JobState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Serializes the state of a job as `queued`, `printing`, `completed` or `failed`. Jobs waiting for
+/// the printer to recover are still printing.
+fn serialize_state<S: serde::Serializer>(state: &JobState, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(match state {
+        JobState::Queued => "queued",
+        JobState::Printing { .. } | JobState::WaitingForRecovery(_) => "printing",
+        JobState::Completed => "completed",
+        JobState::Failed(_) => "failed",
+    })
+}
+
+/// The status of a printer, which isn't asked while it is printing
+#[derive(Debug, Serialize)]
+pub struct StatusReport {
+    /// The status the printer reported, `None` if it was never asked yet
+    pub status: Option<Value>,
+    /// Whether `status` was reported before the jobs in progress were started
+    pub cached: bool,
+    /// The oldest job that didn't finish yet
+    pub job: Option<JobInfo>,
+}
+
+/// A job submitted to the spooler of a printer
+struct Job {
+    printer: String,
+    pages: usize,
+    handle: JobHandle,
+}
+
+impl Job {
+    fn info(&self, id: u64) -> JobInfo {
+        let state = self.handle.state();
+        let error = match &state {
+            JobState::Failed(error) => Some(error.clone()),
+            JobState::WaitingForRecovery(errors) => Some(errors.join(", ")),
+            _ => None,
+        };
+        JobInfo {
+            id,
+            printer: self.printer.clone(),
+            pages: self.pages,
+            state,
+            error,
+        }
+    }
+}
+
+pub struct Printer {
+    pub info: PrinterInfo,
+    spooler: Spooler,
+    /// The label loaded when the printer was last asked
+    label: Mutex<Option<Label>>,
+    /// The status the printer reported when it was last asked
+    last_status: Mutex<Option<Value>>,
+}
+
+impl Printer {
+    /// Asks the printer for its status, waiting for the queued jobs to finish.
+    pub fn status(&self) -> brother_ql_rs::printer::Result<status::Response> {
+        let status = self.spooler.status()?;
+        *self.label.lock().unwrap() = status.media.label();
+        *self.last_status.lock().unwrap() = serde_json::to_value(&status).ok();
+        Ok(status)
+    }
+
+    /// Asks the printer for its status, unless it is busy printing.
+    pub fn status_if_idle(&self) -> Option<brother_ql_rs::printer::Result<status::Response>> {
+        if !self.spooler.is_idle() {
+            return None;
+        }
+        Some(self.status())
+    }
+
+    pub fn last_known_label(&self) -> Option<Label> {
+        *self.label.lock().unwrap()
+    }
+
+    /// The label to lay out new jobs for: the loaded label if the printer is idle, otherwise the label
+    /// the queued jobs were laid out for.
+    pub fn label(&self) -> brother_ql_rs::printer::Result<Label> {
+        let label = match self.status_if_idle() {
+            Some(status) => status?.media.label(),
+            None => self.last_known_label(),
+        };
+        label.ok_or_else(|| "The loaded media is unknown".into())
+    }
+}
+
+#[derive(Default)]
+pub struct Queue {
+    printers: BTreeMap<String, Printer>,
+    jobs: Mutex<BTreeMap<u64, Job>>,
+    next_id: AtomicU64,
+    /// Records the jobs of every printer added afterwards
+    pub journal: Option<Arc<Journal>>,
+    /// Tracks the roll usage of every printer added afterwards
+    pub usage: Option<Arc<UsageTracker>>,
+}
+
+impl Queue {
+    /// Starts a spooler for the printer, making it available by its serial number.
+    pub fn add_printer(
+        &mut self,
+        mut printer: ThermalPrinter<GlobalContext>,
+    ) -> brother_ql_rs::printer::Result<()> {
+        if let Some(journal) = &self.journal {
+            printer.set_journal(journal.clone());
+        }
+        if let Some(usage) = &self.usage {
+            printer.set_usage_tracker(usage.clone());
+        }
+        let info = PrinterInfo {
+            serial: printer.serial_number.clone(),
+            manufacturer: printer.manufacturer.clone(),
+            model: printer.model.clone(),
+        };
+        let spooler = Spooler::new(printer)?;
+
+        info!("Serving {} {} ({})", info.manufacturer, info.model, info.serial);
+        self.printers.insert(
+            info.serial.clone(),
+            Printer {
+                info,
+                spooler,
+                label: Mutex::new(None),
+                last_status: Mutex::new(None),
+            },
+        );
+        Ok(())
+    }
+
+    pub fn printers(&self) -> impl Iterator<Item = &Printer> {
+        self.printers.values()
+    }
+
+    pub fn printer(&self, serial: &str) -> Option<&Printer> {
+        self.printers.get(serial)
+    }
+
+    pub fn job(&self, id: u64) -> Option<JobInfo> {
+        self.jobs.lock().unwrap().get(&id).map(|job| job.info(id))
+    }
+
+    /// Jobs sent to the printer with the given serial number that are still remembered, oldest first
+    pub fn jobs(&self, serial: &str) -> Vec<JobInfo> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, job)| job.printer == serial)
+            .map(|(&id, job)| job.info(id))
+            .collect()
+    }
+
+    /// Asks the printer for its status if it is idle. While it is printing, returns the status it
+    /// reported last instead, so the request doesn't wait for the queued jobs.
+    pub fn status_report(&self, printer: &Printer) -> brother_ql_rs::printer::Result<StatusReport> {
+        let job = self
+            .jobs(&printer.info.serial)
+            .into_iter()
+            .find(|job| !job.state.is_finished());
+        let cached = match printer.status_if_idle() {
+            Some(status) => {
+                status?;
+                false
+            }
+            None => true,
+        };
+        Ok(StatusReport {
+            status: printer.last_status.lock().unwrap().clone(),
+            cached,
+            job,
+        })
+    }
+
+    /// Queues pages laid out with `lay_out` to be printed on the printer.
+    pub fn submit(&self, printer: &Printer, pages: Vec<PrintJob>) -> JobInfo {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let job = Job {
+            printer: printer.info.serial.clone(),
+            pages: pages.len(),
+            handle: printer.spooler.submit_pages(pages),
+        };
+        let info = job.info(id);
+        let mut jobs = self.jobs.lock().unwrap();
+        forget_finished(&mut jobs);
+        jobs.insert(id, job);
+        info
+    }
+}
+
+/// Lays out images for the label, one page each.
+pub fn lay_out(label: &Label, pages: &[Image]) -> brother_ql_rs::printer::Result<Vec<PrintJob>> {
+    pages.iter().map(|image| lay_out_page(label, image)).collect()
+}
+
+/// Lays out an image for the label as a single page.
+pub fn lay_out_page(label: &Label, image: &Image) -> brother_ql_rs::printer::Result<PrintJob> {
+    PrintJob::from_label_page(&image.pixels, image.width, label, Resolution::Normal)
+}
+
+fn forget_finished(jobs: &mut BTreeMap<u64, Job>) {
+    let finished: Vec<u64> = jobs
+        .iter()
+        .filter(|(_, job)| job.handle.state().is_finished())
+        .map(|(&id, _)| id)
+        .collect();
+    for id in finished.iter().take(finished.len().saturating_sub(FINISHED_JOBS_KEPT)) {
+        jobs.remove(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn serializes_job_states() {
+        let job = |state| JobInfo {
+            id: 3,
+            printer: "A1B2".to_string(),
+            pages: 2,
+            state,
+            error: None,
+        };
+        assert_eq!(
+            serde_json::to_value(job(JobState::Queued)).unwrap(),
+            json!({"id": 3, "printer": "A1B2", "pages": 2, "state": "queued"})
+        );
+        let states = [
+            (JobState::Printing { attempt: 2 }, "printing"),
+            (JobState::WaitingForRecovery(vec!["Cover open"]), "printing"),
+            (JobState::Completed, "completed"),
+            (JobState::Failed("Cover open".to_string()), "failed"),
+        ];
+        for (state, name) in states {
+            assert_eq!(serde_json::to_value(job(state)).unwrap()["state"], name);
+        }
+    }
+}
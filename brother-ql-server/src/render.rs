@@ -0,0 +1,219 @@
+//! Turning uploaded content into grayscale images
+use std::collections::HashMap;
+
+use brother_ql_rs::printer::raster::RasterPage;
+use noto_sans_mono_bitmap::{get_raster, get_raster_width, FontWeight, RasterHeight};
+
+const RASTER_HEIGHT: RasterHeight = RasterHeight::Size32;
+const FONT_WEIGHT: FontWeight = FontWeight::Regular;
+/// Resolution of the print head, in both directions
+const DPI: u32 = 300;
+
+/// A grayscale image with one byte per pixel, 0 being black
+#[derive(Clone)]
+pub struct Image {
+    pub width: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    /// Width and height of a raster page once it was converted by `from_raster`
+    pub fn raster_size(page: &RasterPage) -> (usize, usize) {
+        match page.resolution {
+            (0, _) | (_, 0) => (page.width, page.height),
+            (x_dpi, y_dpi) => (
+                (page.width.saturating_mul(DPI as usize) / x_dpi as usize).max(1),
+                (page.height.saturating_mul(DPI as usize) / y_dpi as usize).max(1),
+            ),
+        }
+    }
+
+    /// Converts a raster page to the resolution of the print head.
+    pub fn from_raster(page: RasterPage) -> Image {
+        let (x_dpi, y_dpi) = page.resolution;
+        if (x_dpi, y_dpi) == (DPI, DPI) || x_dpi == 0 || y_dpi == 0 {
+            return Image {
+                width: page.width,
+                pixels: page.pixels,
+            };
+        }
+        let (width, height) = Image::raster_size(&page);
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let source_y = (y * y_dpi as usize / DPI as usize).min(page.height - 1);
+            for x in 0..width {
+                let source_x = (x * x_dpi as usize / DPI as usize).min(page.width - 1);
+                pixels.push(page.pixels[source_y * page.width + source_x]);
+            }
+        }
+        Image { width, pixels }
+    }
+}
+
+/// Decodes a PNG image of any color type. Transparent pixels are white.
+pub fn decode_png(data: &[u8]) -> Result<Image, String> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|error| error.to_string())?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut buffer)
+        .map_err(|error| error.to_string())?;
+
+    let channels = info.color_type.samples();
+    let pixels = buffer[..info.buffer_size()]
+        .chunks(channels)
+        .map(|pixel| {
+            let (gray, alpha) = match pixel {
+                [gray] => (*gray as u32, 0xFF),
+                [gray, alpha] => (*gray as u32, *alpha as u32),
+                [r, g, b] => (luma(*r, *g, *b), 0xFF),
+                [r, g, b, alpha] => (luma(*r, *g, *b), *alpha as u32),
+                _ => (0xFF, 0),
+            };
+            // Blend onto white
+            ((gray * alpha + 0xFF * (0xFF - alpha)) / 0xFF) as u8
+        })
+        .collect();
+    Ok(Image {
+        width: info.width as usize,
+        pixels,
+    })
+}
+
+fn luma(r: u8, g: u8, b: u8) -> u32 {
+    (299 * r as u32 + 587 * g as u32 + 114 * b as u32) / 1000
+}
+
+/// Replaces every `{field}` in the template with its value from `data`, in a single pass, so values
+/// are never substituted into. Placeholders of fields without a value are kept.
+pub fn fill_template(template: &str, data: &HashMap<String, String>) -> String {
+    let mut text = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        let value = rest
+            .find(['{', '}'])
+            .filter(|&end| rest[end..].starts_with('}'))
+            .and_then(|end| Some((data.get(&rest[..end])?, end)));
+        match value {
+            Some((value, end)) => {
+                text.push_str(value);
+                rest = &rest[end + 1..];
+            }
+            None => text.push('{'),
+        }
+    }
+    text.push_str(rest);
+    text
+}
+
+/// Width and height in pixels of text rendered by `render_text`
+pub fn text_size(text: &str, scale: usize) -> (usize, usize) {
+    let char_width = get_raster_width(FONT_WEIGHT, RASTER_HEIGHT) * scale;
+    let line_height = RASTER_HEIGHT.val() * scale;
+    let columns = text
+        .lines()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0);
+    (
+        (columns * char_width).max(1),
+        (text.lines().count() * line_height).max(1),
+    )
+}
+
+/// Renders text in a monospace font, each character pixel enlarged to `scale` by `scale` dots.
+pub fn render_text(text: &str, scale: usize) -> Image {
+    let char_width = get_raster_width(FONT_WEIGHT, RASTER_HEIGHT) * scale;
+    let line_height = RASTER_HEIGHT.val() * scale;
+    let lines: Vec<&str> = text.lines().collect();
+
+    let (width, height) = text_size(text, scale);
+    let mut pixels = vec![0xFF; width * height];
+    for (line_index, line) in lines.iter().enumerate() {
+        for (char_index, c) in line.chars().enumerate() {
+            let raster = get_raster(c, FONT_WEIGHT, RASTER_HEIGHT)
+                .unwrap_or_else(|| get_raster('?', FONT_WEIGHT, RASTER_HEIGHT).unwrap());
+            for (row, intensities) in raster.raster().iter().enumerate() {
+                for (column, &intensity) in intensities.iter().enumerate() {
+                    for dy in 0..scale {
+                        let y = line_index * line_height + row * scale + dy;
+                        let x = char_index * char_width + column * scale;
+                        pixels[y * width + x..y * width + x + scale].fill(0xFF - intensity);
+                    }
+                }
+            }
+        }
+    }
+    Image { width, pixels }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use brother_ql_rs::printer::raster::CutMedia;
+
+    fn data(fields: &[(&str, &str)]) -> HashMap<String, String> {
+        fields
+            .iter()
+            .map(|(field, value)| (field.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn fills_templates() {
+        let data = data(&[("name", "Ada"), ("room", "{name}"), ("x", "1")]);
+        assert_eq!(fill_template("Hi {name}, {name}!", &data), "Hi Ada, Ada!");
+        // Values aren't substituted into, whatever order the fields are in
+        assert_eq!(fill_template("{room} {name}", &data), "{name} Ada");
+        assert_eq!(fill_template("{unknown} {", &data), "{unknown} {");
+        assert_eq!(fill_template("{{x}} {x", &data), "{1} {x");
+        assert_eq!(fill_template("", &data), "");
+    }
+
+    #[test]
+    fn renders_text() {
+        let (width, height) = text_size("ab\nc", 2);
+        let image = render_text("ab\nc", 2);
+        assert_eq!(image.width, width);
+        assert_eq!(image.pixels.len(), width * height);
+        assert_eq!(height, RASTER_HEIGHT.val() * 2 * 2);
+        assert!(image.pixels.iter().any(|&pixel| pixel < 0x80));
+        assert_eq!(text_size("", 1), (1, 1));
+    }
+
+    #[test]
+    fn scales_raster_pages_to_the_print_head() {
+        let page = |resolution| RasterPage {
+            width: 2,
+            height: 1,
+            resolution,
+            page_size: (0, 0),
+            page_size_name: String::new(),
+            copies: 1,
+            cut_media: CutMedia::Never,
+            pixels: vec![0, 0xFF],
+        };
+        let image = Image::from_raster(page((150, 300)));
+        assert_eq!(image.width, 4);
+        assert_eq!(image.pixels, [0, 0, 0xFF, 0xFF]);
+        assert_eq!(Image::raster_size(&page((600, 150))), (1, 2));
+        assert_eq!(Image::from_raster(page((300, 300))).pixels, [0, 0xFF]);
+    }
+
+    #[test]
+    fn decodes_transparent_png_as_white() {
+        let mut data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut data, 2, 1);
+        encoder.set_color(png::ColorType::GrayscaleAlpha);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[0, 0xFF, 0, 0]).unwrap();
+        writer.finish().unwrap();
+        let image = decode_png(&data).unwrap();
+        assert_eq!(image.width, 2);
+        assert_eq!(image.pixels, [0, 0xFF]);
+        assert!(decode_png(b"not a png").is_err());
+    }
+}
@@ -0,0 +1,115 @@
+//! Forwarding the command stream of a connection to a printer
+//!
+//! Commands are decoded as they arrive. Print jobs are collected up to their last page and checked
+//! with `decoder::check_job` before anything is sent to the printer, so invalid or aborted streams
+//! never leave the printer in the middle of a job. Status requests are answered right away with the
+//! status message of the printer, and the status messages the printer sends while printing are
+//! passed on to the client as they arrive. If the printer stops sending them for the progress timeout
+//! of its configuration, the connection is dropped with an error.
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use brother_ql_rs::printer::decoder::{check_job, decode_next, Instruction};
+use brother_ql_rs::printer::{Result, ThermalPrinter};
+use log::{debug, info, warn};
+use rusb::GlobalContext;
+
+/// Connections are closed after this long without receiving anything
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+/// Largest job accepted, a meter of tape at high resolution takes about 2 MiB
+const MAX_JOB_SIZE: usize = 32 * 1024 * 1024;
+
+/// Commands received since the last job was printed
+#[derive(Default)]
+struct Job {
+    data: Vec<u8>,
+    instructions: Vec<Instruction>,
+}
+
+impl Job {
+    /// Whether the commands so far belong to a page, rather than only changing settings
+    fn has_pages(&self) -> bool {
+        self.instructions.iter().any(|instruction| {
+            matches!(
+                instruction,
+                Instruction::PrintInfo(_)
+                    | Instruction::RasterLine(_)
+                    | Instruction::ZeroLine
+                    | Instruction::Print
+            )
+        })
+    }
+}
+
+/// Forwards everything received on the connection to the printer until the client closes it,
+/// returning the number of jobs printed.
+pub fn handle(mut stream: TcpStream, printer: &ThermalPrinter<GlobalContext>) -> Result<usize> {
+    stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+    let mut received = Vec::new();
+    let mut job = Job::default();
+    let mut jobs = 0;
+    let mut buffer = [0; 16 * 1024];
+    loop {
+        let length = match stream.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(length) => length,
+            Err(error) if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                info!("Closing idle connection");
+                break;
+            }
+            Err(error) => return Err(error.into()),
+        };
+        received.extend_from_slice(&buffer[..length]);
+
+        let mut position = 0;
+        while let Some((instruction, length)) = decode_next(&received[position..])? {
+            let data = &received[position..position + length];
+            position += length;
+            match instruction {
+                Instruction::GetStatus => {
+                    printer.write_raw(data)?;
+                    stream.write_all(&printer.read_status_message()?)?;
+                }
+                Instruction::PrintLast => {
+                    job.data.extend_from_slice(data);
+                    job.instructions.push(instruction);
+                    print(printer, &mut stream, &job)?;
+                    job = Job::default();
+                    jobs += 1;
+                }
+                _ => {
+                    job.data.extend_from_slice(data);
+                    job.instructions.push(instruction);
+                }
+            }
+            if job.data.len() > MAX_JOB_SIZE {
+                return Err(format!("Job is larger than {} bytes", MAX_JOB_SIZE).into());
+            }
+        }
+        received.drain(..position);
+    }
+
+    if !received.is_empty() {
+        warn!("Connection closed in the middle of a command");
+    }
+    if job.has_pages() {
+        warn!("Discarding a job without its last page");
+    } else if !job.data.is_empty() {
+        debug!("Forwarding {} bytes of settings", job.data.len());
+        printer.write_raw(&job.data)?;
+    }
+    Ok(jobs)
+}
+
+fn print(
+    printer: &ThermalPrinter<GlobalContext>,
+    stream: &mut TcpStream,
+    job: &Job,
+) -> Result<()> {
+    let pages = check_job(&job.instructions)?;
+    info!("Printing {} pages on {:?}", pages, printer);
+    printer.write_raw(&job.data)?;
+    printer.wait_for_completion_forwarding(pages, |message| Ok(stream.write_all(message)?))?;
+    Ok(())
+}
@@ -0,0 +1,110 @@
+//! Raw socket print server for Brother QL printers
+//!
+//! Listens on a TCP port for every printer attached over USB when the server starts, like the
+//! JetDirect port 9100 of network printers, so software printing to `socket://host:9100` can print
+//! to USB printers. See `connection` for how command streams are forwarded.
+//!
+//! Usage: `brother-ql-socket [--listen ADDRESS] [--port PORT] [--timeout SECONDS]`. Printers are
+//! served on consecutive ports starting at 9100, ordered by serial number, on all addresses by
+//! default. Connections are dropped when a printer reports no progress on a job for the timeout, 60
+//! seconds by default.
+use std::env;
+use std::net::TcpListener;
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+use brother_ql_rs::printer::config::PrinterConfig;
+use brother_ql_rs::printer::{printers, ThermalPrinter};
+use log::{error, info, warn};
+
+mod connection;
+
+const DEFAULT_ADDRESS: &str = "0.0.0.0";
+const DEFAULT_PORT: u16 = 9100;
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let mut address = DEFAULT_ADDRESS.to_string();
+    let mut port = DEFAULT_PORT;
+    let mut config = PrinterConfig::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--listen", Some(value)) => address = value,
+            ("--port", Some(value)) => match value.parse() {
+                Ok(value) => port = value,
+                Err(_) => usage(),
+            },
+            ("--timeout", Some(value)) => match value.parse() {
+                Ok(seconds) => config.progress_timeout = Duration::from_secs(seconds),
+                Err(_) => usage(),
+            },
+            _ => usage(),
+        }
+    }
+
+    let mut printers: Vec<_> = printers()
+        .into_iter()
+        .filter_map(|device| match ThermalPrinter::with_config(device, config.clone()) {
+            Ok(printer) => Some(printer),
+            Err(error) => {
+                warn!("Skipping printer: {}", error);
+                None
+            }
+        })
+        .collect();
+    if printers.is_empty() {
+        error!("No printers found");
+        process::exit(1);
+    }
+    printers.sort_by(|a, b| a.serial_number.cmp(&b.serial_number));
+
+    let mut servers = Vec::new();
+    for (printer, port) in printers.into_iter().zip(port..) {
+        let listener = match TcpListener::bind((address.as_str(), port)) {
+            Ok(listener) => listener,
+            Err(error) => {
+                error!("Can't listen on {}:{}: {}", address, port, error);
+                process::exit(1);
+            }
+        };
+        info!("Serving {:?} on {}:{}", printer, address, port);
+        servers.push(thread::spawn(move || serve(listener, printer)));
+    }
+    for server in servers {
+        let _ = server.join();
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: brother-ql-socket [--listen ADDRESS] [--port PORT] [--timeout SECONDS]");
+    process::exit(2);
+}
+
+/// Serves one connection after another, as the printer can only print one job at a time.
+fn serve(listener: TcpListener, printer: ThermalPrinter<rusb::GlobalContext>) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                warn!("Can't accept connection: {}", error);
+                continue;
+            }
+        };
+        let peer = stream
+            .peer_addr()
+            .map(|address| address.to_string())
+            .unwrap_or_default();
+        info!("Connection from {} to {:?}", peer, printer);
+        match connection::handle(stream, &printer) {
+            Ok(jobs) => info!("Printed {} jobs from {}", jobs, peer),
+            Err(error) => warn!("Connection from {} failed: {}", peer, error),
+        }
+        if !printer.is_connected() {
+            error!("{:?} was disconnected, no longer serving it", printer);
+            return;
+        }
+    }
+}
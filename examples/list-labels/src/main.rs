@@ -0,0 +1,21 @@
+use brother_ql_rs::printer::catalogue::{catalogue, Shape};
+
+fn main() {
+    println!("{:<8} {:<10} {:>12} {:>12}  Part numbers", "Name", "Shape", "Dots", "Printable");
+    for entry in catalogue() {
+        let shape = match entry.shape {
+            Shape::Continuous => "continuous",
+            Shape::Rectangle => "die-cut",
+            Shape::Round => "round",
+        };
+        let label = entry.label;
+        println!(
+            "{:<8} {:<10} {:>12} {:>12}  {}",
+            entry.name,
+            shape,
+            format!("{}x{}", label.dots.0, label.dots.1),
+            format!("{}x{}", label.dots_printable.0, label.dots_printable.1),
+            entry.part_numbers.join(", ")
+        );
+    }
+}
@@ -3,7 +3,6 @@ extern crate brother_ql_rs;
 use barcoders::sym::ean13::EAN13;
 use brother_ql_rs::printer::constants::MAX_PIXEL_WIDTH;
 use brother_ql_rs::printer::job::PrintJob;
-use brother_ql_rs::printer::setting::Resolution;
 use brother_ql_rs::printer::{printers, Printable, ThermalPrinter};
 
 const BAR_HEIGHT: usize = 80;
@@ -33,12 +32,8 @@ fn main() {
     }
 
     let line = [row.into_raster_line()];
-    let job = PrintJob {
-        cut_on_end: true,
-        raster_lines: line.repeat(BAR_HEIGHT),
-        resolution: Resolution::Normal,
-        mirrored: true,
-    };
+    let mut job = PrintJob::new(line.repeat(BAR_HEIGHT));
+    job.mirrored = true;
 
     for printer in printers() {
         match ThermalPrinter::new(printer) {
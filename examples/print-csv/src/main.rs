@@ -0,0 +1,65 @@
+use std::env;
+use noto_sans_mono_bitmap::{get_raster, get_raster_width, FontWeight, RasterHeight};
+use brother_ql_rs::printer::batch::{Batch, BatchOptions, Record, SerialField};
+use brother_ql_rs::printer::constants::{MAX_PIXEL_WIDTH, RASTER_LINE_LENGTH};
+use brother_ql_rs::printer::job::PrintJob;
+use brother_ql_rs::printer::{printers, Printable, ThermalPrinter};
+
+const RASTER_HEIGHT: RasterHeight = RasterHeight::Size16;
+const FONT_WEIGHT: FontWeight = FontWeight::Regular;
+
+fn render_text(text: &str, lines: &mut Vec<[u8; RASTER_LINE_LENGTH]>) {
+    let char_width = get_raster_width(FONT_WEIGHT, RASTER_HEIGHT);
+    let mut rows = vec![[true; MAX_PIXEL_WIDTH]; RASTER_HEIGHT.val()];
+    for (char_i, c) in text.chars().enumerate() {
+        let char_raster = get_raster(c, FONT_WEIGHT, RASTER_HEIGHT)
+            .unwrap_or_else(|| get_raster('?', FONT_WEIGHT, RASTER_HEIGHT).unwrap());
+        for (row_i, row) in char_raster.raster().iter().enumerate() {
+            for (col_i, &pixel) in row.iter().enumerate() {
+                let x = char_i * char_width + col_i;
+                if x < MAX_PIXEL_WIDTH {
+                    rows[row_i][x] = pixel < 128
+                }
+            }
+        }
+    }
+    lines.extend(rows.into_iter().map(|row| row.into_raster_line()));
+}
+
+fn render(record: &Record) -> brother_ql_rs::printer::Result<PrintJob> {
+    let mut lines = vec![];
+    render_text(&record["name"], &mut lines);
+    render_text(&format!("{} #{}", record["location"], record["serial"]), &mut lines);
+
+    let mut job = PrintJob::new(lines);
+    job.mirrored = true;
+    Ok(job)
+}
+
+fn main() {
+    let batch = Batch::from_csv_path("labels.csv").unwrap();
+    let options = BatchOptions {
+        copies: 2,
+        serial_fields: vec![SerialField { width: 4, ..SerialField::new("serial", 1) }],
+        ..BatchOptions::default()
+    };
+
+    if env::args().any(|arg| arg == "--dry-run") {
+        for page in batch.render(&options, render).unwrap() {
+            println!("{}", page.to_image(None).to_terminal(80));
+        }
+        let paths = batch.dry_run(".", &options, render).unwrap();
+        println!("Rendered {} labels", paths.len());
+        return;
+    }
+
+    for printer in printers() {
+        match ThermalPrinter::new(printer) {
+            Ok(p) => {
+                println!("Sending {} labels to printer...", batch.records().len() * options.copies);
+                batch.print(&p, &options, render).unwrap()
+            },
+            Err(e) => panic!("Failed to init Thermal Printer: {:?}", e)
+        };
+    }
+}
@@ -17,7 +17,7 @@ fn main() {
     let bytes_total = bytes.len();
     println!("bytes: {}", bytes_total);
 
-    let lines = bytes.chunks(RASTER_LINE_LENGTH)
+    let lines: Vec<[u8; RASTER_LINE_LENGTH]> = bytes.chunks(RASTER_LINE_LENGTH)
         .map(|chunk| {
             let mut row = [255u8; RASTER_LINE_LENGTH];
             for (x, &byte) in chunk.iter().enumerate() {
@@ -27,12 +27,11 @@ fn main() {
         })
         .collect();
 
-    let job = PrintJob {
-        cut_on_end: true,
-        raster_lines: lines,
-        resolution: Resolution::Normal,
-        mirrored: true,
-    };
+    // The image has square pixels, high resolution jobs need every line twice
+    let resolution = Resolution::Normal;
+    let mut job = PrintJob::new(resolution.scale_lines(&lines));
+    job.resolution = resolution;
+    job.mirrored = true;
 
     for printer in printers() {
         match ThermalPrinter::new(printer) {
@@ -1,8 +1,6 @@
 use qrcode::{Color, QrCode};
-use brother_ql_rs::printer::constants::{MAX_PIXEL_WIDTH, RASTER_LINE_LENGTH};
 use brother_ql_rs::printer::job::PrintJob;
-use brother_ql_rs::printer::{Printable, printers, ThermalPrinter};
-use brother_ql_rs::printer::setting::Resolution;
+use brother_ql_rs::printer::{printers, ThermalPrinter};
 
 const BLOCK_SIZE: usize = 8;
 
@@ -10,34 +8,27 @@ fn main() {
     let code = QrCode::new(b"01234567").unwrap();
     let data = code.to_colors();
 
-    let offset = 0;
     let size = code.width();
+    let width = size * BLOCK_SIZE;
 
-    let mut lines: Vec<[u8; RASTER_LINE_LENGTH]> = vec![];
+    let mut pixels = vec![];
     for y in 0..size {
-        let mut row = [true; MAX_PIXEL_WIDTH];
+        let mut row = vec![];
         for x in 0..size {
-            for p in 0..BLOCK_SIZE {
-                row[offset + x * BLOCK_SIZE + p] = data[y * size + x] == Color::Light;
-            }
+            let gray = if data[y * size + x] == Color::Light { 255 } else { 0 };
+            row.extend([gray; BLOCK_SIZE]);
         }
-
-        let line = row.into_raster_line();
         for _ in 0..BLOCK_SIZE {
-            lines.push(line);
+            pixels.extend_from_slice(&row);
         }
     }
 
-    let job = PrintJob {
-        cut_on_end: true,
-        raster_lines: lines,
-        resolution: Resolution::Normal,
-        mirrored: true
-    };
-
     for printer in printers() {
         match ThermalPrinter::new(printer) {
             Ok(p) => {
+                // Places the code within the printable area of the loaded media
+                let label = p.current_label().unwrap();
+                let job = PrintJob::from_grayscale(&pixels, width, &label).unwrap();
                 println!("Sending job to printer...");
                 p.print(&job).unwrap()
             },
@@ -1,9 +1,7 @@
 
 use noto_sans_mono_bitmap::{get_raster, get_raster_width, FontWeight, RasterHeight};
 use brother_ql_rs::printer::job::PrintJob;
-use brother_ql_rs::printer::{Printable, printers, ThermalPrinter};
-use brother_ql_rs::printer::constants::MAX_PIXEL_WIDTH;
-use brother_ql_rs::printer::setting::Resolution;
+use brother_ql_rs::printer::{printers, ThermalPrinter};
 
 fn main() {
     let raster_height = RasterHeight::Size16;
@@ -21,29 +19,18 @@ fn main() {
             .expect("unsupported char");
     println!("{:?}", char_raster);
 
-    let mut lines = vec![];
-    for &row in char_raster.raster().iter() {
-        let mut line = [true; MAX_PIXEL_WIDTH];
-
-        for (col_i, &pixel) in row.iter().enumerate() {
-            if col_i < MAX_PIXEL_WIDTH {
-                line[col_i] = pixel < 128
-            }
-        }
-
-        lines.push(line.into_raster_line())
-    }
-
-    let job = PrintJob {
-        cut_on_end: true,
-        raster_lines: lines,
-        resolution: Resolution::Normal,
-        mirrored: true
-    };
+    // Black text on white, placed within the printable area of the loaded media
+    let pixels: Vec<u8> = char_raster
+        .raster()
+        .iter()
+        .flat_map(|row| row.iter().map(|&intensity| 255 - intensity))
+        .collect();
 
     for printer in printers() {
         match ThermalPrinter::new(printer) {
             Ok(p) => {
+                let label = p.current_label().unwrap();
+                let job = PrintJob::from_grayscale(&pixels, width, &label).unwrap();
                 println!("Sending job to printer...");
                 p.print(&job).unwrap()
             },